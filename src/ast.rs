@@ -1,19 +1,38 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use colored::Colorize;
 
-#[derive(Debug, Clone)]
+use crate::{
+    lexer::{ParseError, Tokenizer, parse},
+    symbol::Symbol,
+    value::{char_literal, format_float},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
-    Symbol(String),
+    Symbol(Symbol),
+    Bool(bool),
+    Int(i64),
     Number(f64),
-    List(Vec<Expr>)
+    /// An exact fraction in lowest terms, `den` being greater than 1.
+    Rational { num: i64, den: i64 },
+    String(String),
+    Char(char),
+    List(Vec<Expr>),
+    Vector(Vec<Expr>)
 }
 
 impl Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expr::Symbol(s) => write!(f, "{}", s.green()),
-            Expr::Number(n) => write!(f, "{}", n.to_string().blue()),
+            Expr::Symbol(s) => write!(f, "{}", s.as_str().green()),
+            Expr::Bool(b) => write!(f, "{}", if *b { "#t" } else { "#f" }.yellow()),
+            Expr::Int(n) => write!(f, "{}", n.to_string().blue()),
+            Expr::Number(n) => write!(f, "{}", float_literal(*n).blue()),
+            Expr::Rational { num, den } => write!(f, "{}", format!("{num}/{den}").blue()),
+            Expr::String(s) => write!(f, "{}", format!("{s:?}").cyan()),
+            Expr::Char(c) => write!(f, "{}", char_literal(*c).magenta()),
             Expr::List(l) => {
                 write!(f, "(")?;
                 for (i, expr) in l.iter().enumerate() {
@@ -25,6 +44,160 @@ impl Display for Expr {
                 write!(f, ")")?;
                 Ok(())
             }
+            Expr::Vector(v) => {
+                write!(f, "#(")?;
+                for (i, expr) in v.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{expr}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Parse exactly one datum, e.g. `"(+ 1 2)".parse::<Expr>()`.
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Tokenizer::new(s).peekable();
+        let expr = parse(&mut tokens)?;
+        match tokens.next().transpose()? {
+            Some(token) => Err(ParseError::UnexpectedToken(token.text, token.span)),
+            None => Ok(expr),
+        }
+    }
+}
+
+/// Render `expr` without colors, breaking lists that don't fit in `width`
+/// columns onto several lines. A broken list keeps its head and first item
+/// on the opening line when the head is a symbol, the remaining items are
+/// indented by two under it.
+pub fn pretty_print(expr: &Expr, width: usize) -> String {
+    let mut out = String::new();
+    pretty(expr, 0, width, &mut out);
+    out
+}
+
+fn pretty(expr: &Expr, indent: usize, width: usize, out: &mut String) {
+    let flat = flat(expr);
+    let (open, items) = match expr {
+        Expr::List(items) if !items.is_empty() => ("(", items),
+        Expr::Vector(items) if !items.is_empty() => ("#(", items),
+        _ => {
+            out.push_str(&flat);
+            return;
+        }
+    };
+    if indent + flat.len() <= width {
+        out.push_str(&flat);
+        return;
+    }
+    out.push_str(open);
+    let inner = indent + open.len();
+    let mut rest = items.iter();
+    if let (Expr::List(_), Some(Expr::Symbol(head))) = (expr, items.first()) {
+        out.push_str(head);
+        rest.next();
+        if let Some(first) = rest.next() {
+            out.push(' ');
+            pretty(first, inner + head.len() + 1, width, out);
+        }
+        for item in rest {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent + 2));
+            pretty(item, indent + 2, width, out);
+        }
+    } else {
+        for (i, item) in rest.enumerate() {
+            if i > 0 {
+                out.push('\n');
+                out.push_str(&" ".repeat(inner));
+            }
+            pretty(item, inner, width, out);
         }
     }
-}
\ No newline at end of file
+    out.push(')');
+}
+
+/// A float as source text that reads back as the same float. Unlike
+/// `format_float` it keeps the fractional part of whole numbers, which would
+/// read back as integers.
+fn float_literal(n: f64) -> String {
+    let text = format_float(n);
+    if text.parse::<i64>().is_ok() {
+        format!("{text}.0")
+    } else {
+        text
+    }
+}
+
+/// `expr` on one line, as `Display` would print it without colors.
+fn flat(expr: &Expr) -> String {
+    let join = |items: &[Expr]| items.iter().map(flat).collect::<Vec<_>>().join(" ");
+    match expr {
+        Expr::Symbol(s) => s.to_string(),
+        Expr::Bool(b) => if *b { "#t" } else { "#f" }.to_string(),
+        Expr::Int(n) => n.to_string(),
+        Expr::Number(n) => float_literal(*n),
+        Expr::Rational { num, den } => format!("{num}/{den}"),
+        Expr::String(s) => format!("{s:?}"),
+        Expr::Char(c) => char_literal(*c),
+        Expr::List(items) => format!("({})", join(items)),
+        Expr::Vector(items) => format!("#({})", join(items)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol;
+
+    #[test]
+    fn from_str_parses_one_datum() {
+        assert_eq!("42".parse::<Expr>().unwrap(), Expr::Int(42));
+        assert_eq!("  foo ".parse::<Expr>().unwrap(), Expr::Symbol(Symbol::new("foo")));
+        assert_eq!(
+            "(+ 1 2)".parse::<Expr>().unwrap(),
+            Expr::List(vec![Expr::Symbol(Symbol::new("+")), Expr::Int(1), Expr::Int(2)])
+        );
+        assert_eq!("(a b".parse::<Expr>(), Err(ParseError::UnexpectedEof));
+        let trailing = "1 2".parse::<Expr>();
+        assert!(matches!(trailing, Err(ParseError::UnexpectedToken(t, _)) if t == "2"));
+        assert_eq!("".parse::<Expr>(), Err(ParseError::UnexpectedEof));
+    }
+
+    #[test]
+    fn pretty_print_breaks_only_long_lists() {
+        let expr: Expr = "(define (f x) (if (< x 0) (- x) x))".parse().unwrap();
+        assert_eq!(pretty_print(&expr, 80), "(define (f x) (if (< x 0) (- x) x))");
+        assert_eq!(pretty_print(&expr, 20), "(define (f x)\n  (if (< x 0)\n    (- x)\n    x))");
+        assert_eq!(pretty_print(&"(1 2 3)".parse().unwrap(), 3), "(1\n 2\n 3)");
+        assert_eq!(pretty_print(&"#(1.0 #\\a)".parse().unwrap(), 80), "#(1.0 #\\a)");
+    }
+
+    #[test]
+    fn dotted_lists_round_trip() {
+        colored::control::set_override(false);
+        let expr: Expr = "(1 2 . 3)".parse().unwrap();
+        assert_eq!(
+            expr,
+            Expr::List(vec![Expr::Int(1), Expr::Int(2), Expr::Symbol(symbol::DOT), Expr::Int(3)])
+        );
+        assert_eq!(expr.to_string(), "(1 2 . 3)");
+        assert_eq!(expr.to_string().parse::<Expr>().unwrap(), expr);
+        assert!("(. 3)".parse::<Expr>().is_err());
+        assert!("(1 . 2 3)".parse::<Expr>().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let expr: Expr = "(define (f x . rest) `(1 2.5 3/4 #(\"s\" #\\a) ,x #t))".parse().unwrap();
+        let json = serde_json::to_string(&expr).unwrap();
+        assert_eq!(serde_json::from_str::<Expr>(&json).unwrap(), expr);
+    }
+}
@@ -1,41 +1,186 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::HashMap,
+    fs::File,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+    time::{Duration, Instant},
+};
 
 use crate::{
-    ast::Expr,
-    eval::EvalError,
-    value::{BuiltinFunc, Value},
+    ast::{Expr, pretty_print},
+    eval::{EvalError, SPECIAL_FORMS, apply, datum_to_expr, eval, quote},
+    lexer::{ParseError, Token, Tokenizer, parse, parse_number, parse_spanned},
+    repl::{Balance, balance},
+    symbol::{self, Symbol},
+    value::{
+        BuiltinFn, BuiltinFunc, Continuation, Promise, UserFunction, Value, ValueKey, format_float,
+        reduce_ratio,
+    },
 };
 
-#[derive(Debug, Clone, Default)]
+/// Numbers the symbols made by `gensym`, so no two calls in a process
+/// return the same symbol. Their `#:` prefix keeps the reader from
+/// producing them too.
+static GENSYM_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Tells apart the continuations made by `call/cc`, so that each escape
+/// unwinds to the right one.
+static CONTINUATION_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Default for [`Env::max_depth`].
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// Default for [`Env::max_stack`], leaving headroom within the 2 MiB stack
+/// of a thread spawned with default settings. Nested evaluation takes a few
+/// kilobytes of native stack per level in release builds and tens of
+/// kilobytes in debug builds, so this is what usually runs out first; the
+/// interpreter binary runs on a thread with a larger stack and raises it.
+pub const DEFAULT_MAX_STACK: usize = 1024 * 1024;
+
+/// A frame of bindings. Cloning an `Env` gives another handle to the same
+/// bindings, so that functions capturing it see later definitions and
+/// assignments.
+#[derive(Debug, Clone)]
 pub struct Env {
-    pub vars: HashMap<String, Value>,
+    pub vars: Rc<RefCell<HashMap<Symbol, Value>>>,
     pub parent: Option<Rc<RefCell<Env>>>,
+    /// How deeply `eval` may nest before giving up with an error.
+    pub max_depth: usize,
+    /// How many bytes of native stack nested `eval`s may take before giving
+    /// up with an error instead of overflowing the stack. It has to be less
+    /// than the stack of the thread evaluating.
+    pub max_stack: usize,
+    /// Current nesting depth of `eval`, shared by every frame derived from
+    /// the same top-level environment.
+    pub(crate) depth: Rc<Cell<usize>>,
+    /// Where on the native stack the outermost `eval` started. Shared like
+    /// `depth`.
+    pub(crate) stack_base: Rc<Cell<usize>>,
+    /// Whether any frame has bound the name of a special form, which then
+    /// has to be looked up before treating it as one. Shared like `depth`.
+    pub(crate) special_forms_bound: Rc<Cell<bool>>,
+    /// Evaluation steps left before `eval` gives up, `None` for no limit.
+    /// Shared like `depth`.
+    pub(crate) fuel: Rc<Cell<Option<u64>>>,
+    /// When `eval_with_timeout` gives up, with the timeout it was given.
+    /// Shared like `depth`.
+    pub(crate) deadline: Rc<Cell<Option<(Instant, Duration)>>>,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new()
+    }
 }
 
 impl Env {
     pub fn new() -> Self {
         let mut env = Env {
-            vars: HashMap::new(),
+            vars: Rc::default(),
             parent: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_stack: DEFAULT_MAX_STACK,
+            depth: Rc::new(Cell::new(0)),
+            stack_base: Rc::new(Cell::new(0)),
+            special_forms_bound: Rc::new(Cell::new(false)),
+            fuel: Rc::new(Cell::new(None)),
+            deadline: Rc::new(Cell::new(None)),
         };
         env.define_builtin();
         env
     }
 
     pub fn new_child(&self) -> Env {
+        Env::child_of(Rc::new(RefCell::new(self.clone())))
+    }
+
+    /// An empty frame whose lookups fall back to `parent`, e.g. the frame
+    /// of a call to a function that captured `parent`.
+    pub fn child_of(parent: Rc<RefCell<Env>>) -> Env {
+        let (max_depth, max_stack, depth, stack_base, special_forms_bound, fuel, deadline) = {
+            let parent = parent.borrow();
+            (
+                parent.max_depth,
+                parent.max_stack,
+                parent.depth.clone(),
+                parent.stack_base.clone(),
+                parent.special_forms_bound.clone(),
+                parent.fuel.clone(),
+                parent.deadline.clone(),
+            )
+        };
         Env {
-            vars: HashMap::new(),
-            parent: Some(Rc::new(RefCell::new(self.clone()))),
+            vars: Rc::default(),
+            parent: Some(parent),
+            max_depth,
+            max_stack,
+            depth,
+            stack_base,
+            special_forms_bound,
+            fuel,
+            deadline,
+        }
+    }
+
+    /// Limit how many more steps `eval` may take, in this environment and
+    /// every frame derived from it, before failing with
+    /// `EvalError::OutOfFuel`, which exception handlers can't catch.
+    /// Each evaluated expression and each tail call is a step. `None`
+    /// lifts the limit.
+    pub fn set_fuel(&self, fuel: Option<u64>) {
+        self.fuel.set(fuel);
+    }
+
+    /// The steps left, `None` if there is no limit.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel.get()
+    }
+
+    /// Account for one evaluation step: take its fuel and check the
+    /// deadline, failing once either has run out.
+    pub(crate) fn step(&self) -> Result<(), Box<EvalError>> {
+        match self.fuel.get() {
+            None => {}
+            Some(0) => return Err(Box::new(EvalError::OutOfFuel)),
+            Some(fuel) => self.fuel.set(Some(fuel - 1)),
+        }
+        match self.deadline.get() {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                Err(Box::new(EvalError::Timeout(timeout)))
+            }
+            _ => Ok(()),
         }
     }
 
-    pub fn define(&mut self, name: &str, value: Value) {
-        self.vars.insert(name.to_string(), value);
+    pub fn define(&mut self, name: impl Into<Symbol>, value: Value) {
+        let name = name.into();
+        if SPECIAL_FORMS.contains(&name) {
+            self.special_forms_bound.set(true);
+        }
+        self.vars.borrow_mut().insert(name, value);
+    }
+
+    /// Assign to an existing binding, in the nearest frame that has one.
+    /// Unlike `define` this never creates a binding.
+    pub fn set(&mut self, name: impl Into<Symbol>, value: Value) -> Result<(), Box<EvalError>> {
+        let name = name.into();
+        if let Some(slot) = self.vars.borrow_mut().get_mut(&name) {
+            *slot = value;
+            return Ok(());
+        }
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().set(name, value),
+            None => Err(Box::new(EvalError::UnboundSymbol(name.to_string()))),
+        }
     }
 
-    pub fn get(&self, name: &str) -> Option<Value> {
-        match self.vars.get(name) {
-            Some(val) => Some(val.clone()),
+    pub fn get(&self, name: impl Into<Symbol>) -> Option<Value> {
+        let name = name.into();
+        let val = self.vars.borrow().get(&name).cloned();
+        match val {
+            Some(val) => Some(val),
             None => {
                 if let Some(parent) = &self.parent {
                     parent.borrow().get(name)
@@ -46,340 +191,3489 @@ impl Env {
         }
     }
 
+    /// Drop every binding of this frame and its parents. A function defined
+    /// with a name keeps itself alive through the environment it captures,
+    /// so the frames reachable from the bindings are emptied as well to break
+    /// those cycles. Data reachable only from here is emptied along the way.
+    pub fn clear(&mut self) {
+        let mut values: Vec<Value> = self.vars.borrow_mut().drain().map(|(_, val)| val).collect();
+        let mut frames: Vec<Rc<RefCell<Env>>> = self.parent.take().into_iter().collect();
+        loop {
+            while let Some(val) = values.pop() {
+                match val {
+                    Value::Function(func) => frames.push(func.env),
+                    Value::CaseLambda(clauses) => {
+                        frames.extend(clauses.iter().map(|func| Rc::clone(&func.env)))
+                    }
+                    Value::Pair(pair) => values.extend([pair.0.clone(), pair.1.clone()]),
+                    Value::Vector(items) => values.append(&mut items.borrow_mut()),
+                    Value::HashTable(table) => {
+                        values.extend(table.borrow_mut().drain().map(|(_, val)| val))
+                    }
+                    Value::Record { fields, .. } => values.append(&mut fields.borrow_mut()),
+                    Value::Values(vals) => values.extend(vals),
+                    Value::Parameter(param) => values.push(param.replace(Value::Unspecified)),
+                    Value::Promise(promise) => match promise.replace(Promise::Forced(Value::Nil)) {
+                        Promise::Delayed { env, .. } => frames.push(env),
+                        Promise::Forced(val) => values.push(val),
+                    },
+                    _ => {}
+                }
+            }
+            // a frame seen again has nothing left to clear
+            let Some(frame) = frames.pop() else {
+                break;
+            };
+            let mut frame = frame.borrow_mut();
+            values.extend(frame.vars.borrow_mut().drain().map(|(_, val)| val));
+            frames.extend(frame.parent.take());
+        }
+    }
+
+    /// Whether `name` has a binding in this frame or one of its parents.
+    pub fn is_bound(&self, name: impl Into<Symbol>) -> bool {
+        let name = name.into();
+        self.vars.borrow().contains_key(&name)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.borrow().is_bound(name))
+    }
+
+    /// The sorted names of every builtin function visible from this frame.
+    pub fn builtin_names(&self) -> Vec<String> {
+        let mut names = match &self.parent {
+            Some(parent) => parent.borrow().builtin_names(),
+            None => Vec::new(),
+        };
+        names.extend(
+            self.vars
+                .borrow()
+                .iter()
+                .filter(|(_, val)| matches!(val, Value::BuiltinFunction(_)))
+                .map(|(name, _)| name.to_string()),
+        );
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The user definitions of this frame as Scheme source that `load_defs`
+    /// can read back. Data and functions that capture nothing but top-level
+    /// bindings are written out, anything else is skipped with a comment
+    /// saying so.
+    pub fn dump_defs(&self) -> String {
+        let mut data = Vec::new();
+        let mut functions = Vec::new();
+        let mut skipped = Vec::new();
+        for (name, val) in self.vars.borrow().iter() {
+            match val {
+                Value::BuiltinFunction(_) => {}
+                Value::Function(func) if self.captures_only_globals(func) => {
+                    functions.push((*name, func.clone()))
+                }
+                _ => match datum_to_expr(val) {
+                    Ok(datum) => data.push((*name, datum)),
+                    Err(_) => skipped.push((*name, val.type_name())),
+                },
+            }
+        }
+        data.sort_by_key(|(name, _)| name.as_str());
+        functions.sort_by_key(|(name, _)| name.as_str());
+        skipped.sort_by_key(|(name, _)| name.as_str());
+
+        let mut out = String::new();
+        for (name, datum) in data {
+            let value = match datum {
+                Expr::Symbol(_) | Expr::List(_) => {
+                    Expr::List(vec![Expr::Symbol(symbol::QUOTE), datum])
+                }
+                _ => datum,
+            };
+            let form = Expr::List(vec![
+                Expr::Symbol(symbol::DEFINE),
+                Expr::Symbol(name),
+                value,
+            ]);
+            out.push_str(&pretty_print(&form, 80));
+            out.push('\n');
+        }
+        for (name, func) in functions {
+            let mut params: Vec<Expr> = func.params.iter().copied().map(Expr::Symbol).collect();
+            if let Some(rest) = func.rest {
+                params.extend([Expr::Symbol(symbol::DOT), Expr::Symbol(rest)]);
+            }
+            let define = Expr::Symbol(symbol::DEFINE);
+            // a function defined under its own name refers to itself by it
+            let form = if func.name.as_deref() == Some(name.as_str()) {
+                params.insert(0, Expr::Symbol(name));
+                vec![define, Expr::List(params), func.body.clone()]
+            } else {
+                let lambda = vec![
+                    Expr::Symbol(symbol::LAMBDA),
+                    Expr::List(params),
+                    func.body.clone(),
+                ];
+                vec![define, Expr::Symbol(name), Expr::List(lambda)]
+            };
+            out.push_str(&pretty_print(&Expr::List(form), 80));
+            out.push('\n');
+        }
+        for (name, type_name) in skipped {
+            out.push_str(&format!("; skipped {name}: a {type_name} can't be saved\n"));
+        }
+        out
+    }
+
+    /// Whether `func` was made at the top level of this frame, so that
+    /// redefining it from source gives the same function.
+    fn captures_only_globals(&self, func: &UserFunction) -> bool {
+        let captured = func.env.borrow();
+        captured.parent.is_none() && Rc::ptr_eq(&captured.vars, &self.vars)
+    }
+
+    /// Evaluate every form of `source` in this frame, like `load` does with
+    /// a file, e.g. to read back what `dump_defs` wrote.
+    pub fn load_defs(&mut self, source: &str) -> Result<(), Box<EvalError>> {
+        self.load_tokens(Tokenizer::new(source))
+    }
+
+    /// Evaluate the forms of `tokens` in this frame in turn, parsing each
+    /// only once the one before it has been evaluated.
+    pub fn load_tokens<I>(&mut self, tokens: I) -> Result<(), Box<EvalError>>
+    where
+        I: Iterator<Item = Result<Token, ParseError>>,
+    {
+        let mut tokens = tokens.peekable();
+        while tokens.peek().is_some() {
+            let (form, spans) =
+                parse_spanned(&mut tokens).map_err(|e| Box::new(EvalError::ParseError(e)))?;
+            eval(&form, self).map_err(|e| e.locate(&form, &spans))?;
+        }
+        Ok(())
+    }
+
     fn define_builtin(&mut self) {
         self.define(
             "+",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
-                    let sum = args
-                        .iter()
-                        .map(|val| match val {
-                            Value::Number(num) => Ok(*num),
-                            _ => Err(EvalError::TypeError {
-                                expected: "Number".to_string(),
-                                found: val.clone(),
-                                in_expr: expr.clone(),
-                            }),
-                        })
-                        .collect::<Result<Vec<f64>, EvalError>>()?
-                        .iter()
-                        .sum();
-                    Ok(Value::Number(sum))
+                func: |args, expr, _env| {
+                    // `(+)` is the empty sum
+                    let mut sum = Num::Int(0);
+                    for val in &args {
+                        sum = sum.add(Num::from_value(val, &expr)?);
+                    }
+                    Ok(sum.into())
                 },
                 name: "+".to_string(),
+                min_args: 0,
+                max_args: None,
             }),
         );
         self.define(
             "-",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
+                func: |args, expr, _env| {
+                    let first = Num::from_value(&args[0], &expr)?;
                     if args.len() == 1 {
-                        if let Value::Number(val) = args[0] {
-                            return Ok(Value::Number(-val));
-                        } else {
-                            return Err(Box::new(EvalError::TypeError {
-                                expected: "Number".to_string(),
-                                found: args[0].clone(),
-                                in_expr: expr.clone(),
-                            }));
-                        }
+                        return Ok(Num::Int(0).sub(first).into());
                     }
-                    let first = if let Value::Number(val) = args[0] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[0].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    let rest = &args[1..];
-                    let sum = rest
-                        .iter()
-                        .map(|val| match val {
-                            Value::Number(num) => Ok(*num),
-                            _ => Err(EvalError::TypeError {
-                                expected: "Number".to_string(),
-                                found: val.clone(),
-                                in_expr: expr.clone(),
-                            }),
-                        })
-                        .collect::<Result<Vec<f64>, EvalError>>()?
-                        .iter()
-                        .sum::<f64>();
-                    Ok(Value::Number(first - sum))
+                    let mut result = first;
+                    for val in &args[1..] {
+                        result = result.sub(Num::from_value(val, &expr)?);
+                    }
+                    Ok(result.into())
                 },
                 name: "-".to_string(),
+                min_args: 1,
+                max_args: None,
             }),
         );
         self.define(
             "*",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
-                    if args.len() < 2 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr,
-                            desc: "Expected at least 2 arguments".to_string(),
-                        }));
+                func: |args, expr, _env| {
+                    // `(*)` is the empty product
+                    let mut product = Num::Int(1);
+                    for val in &args {
+                        product = product.mul(Num::from_value(val, &expr)?);
                     }
-                    let product = args
-                        .iter()
-                        .map(|val| match val {
-                            Value::Number(num) => Ok(*num),
-                            _ => Err(EvalError::TypeError {
-                                expected: "Number".to_string(),
-                                found: val.clone(),
-                                in_expr: expr.clone(),
-                            }),
-                        })
-                        .collect::<Result<Vec<f64>, EvalError>>()?
-                        .iter()
-                        .product();
-                    Ok(Value::Number(product))
+                    Ok(product.into())
                 },
                 name: "*".to_string(),
+                min_args: 0,
+                max_args: None,
             }),
         );
         self.define(
             "/",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
+                func: |args, expr, _env| {
+                    let first = Num::from_value(&args[0], &expr)?;
                     if args.len() == 1 {
-                        if let Value::Number(val) = args[0] {
-                            return Ok(Value::Number(1.0 / val));
-                        } else {
-                            return Err(Box::new(EvalError::TypeError {
-                                expected: "Number".to_string(),
-                                found: args[0].clone(),
-                                in_expr: expr.clone(),
-                            }));
-                        }
+                        return Ok(Num::Int(1).div(first, &expr)?.into());
                     }
-                    let first = if let Value::Number(val) = args[0] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[0].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    let rest = &args[1..];
-                    let product = rest
-                        .iter()
-                        .map(|val| match val {
-                            Value::Number(num) => Ok(*num),
-                            _ => Err(EvalError::TypeError {
-                                expected: "Number".to_string(),
-                                found: val.clone(),
-                                in_expr: expr.clone(),
-                            }),
-                        })
-                        .collect::<Result<Vec<f64>, EvalError>>()?
-                        .iter()
-                        .product::<f64>();
-                    Ok(Value::Number(first / product))
+                    let mut result = first;
+                    for val in &args[1..] {
+                        result = result.div(Num::from_value(val, &expr)?, &expr)?;
+                    }
+                    Ok(result.into())
                 },
                 name: "/".to_string(),
+                min_args: 1,
+                max_args: None,
             }),
         );
         self.define(
             "<",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
-                    if args.len() != 2 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr,
-                            desc: "Expected 2 arguments".to_string(),
-                        }));
-                    }
-                    let first = if let Value::Number(val) = args[0] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[0].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    let second = if let Value::Number(val) = args[1] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[1].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    Ok(Value::Bool(first < second))
-                },
+                func: |args, expr, _env| compare(args, expr, |ord| ord == Some(Ordering::Less)),
                 name: "<".to_string(),
+                min_args: 2,
+                max_args: Some(2),
             }),
         );
         self.define(
             "<=",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
-                    if args.len() != 2 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr,
-                            desc: "Expected 2 arguments".to_string(),
-                        }));
-                    }
-                    let first = if let Value::Number(val) = args[0] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[0].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    let second = if let Value::Number(val) = args[1] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[1].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    Ok(Value::Bool(first <= second))
+                func: |args, expr, _env| {
+                    compare(args, expr, |ord| {
+                        matches!(ord, Some(Ordering::Less | Ordering::Equal))
+                    })
                 },
                 name: "<=".to_string(),
+                min_args: 2,
+                max_args: Some(2),
             }),
         );
         self.define(
             ">",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
-                    if args.len() != 2 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr,
-                            desc: "Expected 2 arguments".to_string(),
-                        }));
-                    }
-                    let first = if let Value::Number(val) = args[0] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[0].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    let second = if let Value::Number(val) = args[1] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[1].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    Ok(Value::Bool(first > second))
-                },
+                func: |args, expr, _env| compare(args, expr, |ord| ord == Some(Ordering::Greater)),
                 name: ">".to_string(),
+                min_args: 2,
+                max_args: Some(2),
             }),
         );
         self.define(
             ">=",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
-                    if args.len() != 2 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr,
-                            desc: "Expected 2 arguments".to_string(),
-                        }));
-                    }
-                    let first = if let Value::Number(val) = args[0] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[0].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    let second = if let Value::Number(val) = args[1] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[1].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    Ok(Value::Bool(first >= second))
+                func: |args, expr, _env| {
+                    compare(args, expr, |ord| {
+                        matches!(ord, Some(Ordering::Greater | Ordering::Equal))
+                    })
                 },
                 name: ">=".to_string(),
+                min_args: 2,
+                max_args: Some(2),
             }),
         );
         self.define(
             "=",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
-                    if args.len() != 2 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr,
-                            desc: "Expected 2 arguments".to_string(),
-                        }));
-                    }
-                    let first = if let Value::Number(val) = args[0] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[0].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    let second = if let Value::Number(val) = args[1] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[1].clone(),
-                            in_expr: expr.clone(),
-                        }));
-                    };
-                    Ok(Value::Bool(first == second))
-                },
+                func: |args, expr, _env| compare(args, expr, |ord| ord == Some(Ordering::Equal)),
                 name: "=".to_string(),
+                min_args: 2,
+                max_args: Some(2),
             }),
         );
         self.define(
             "!=",
             Value::BuiltinFunction(BuiltinFunc {
-                func: |args, expr| {
-                    if args.len() != 2 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr,
-                            desc: "Expected 2 arguments".to_string(),
-                        }));
-                    }
-                    let first = if let Value::Number(val) = args[0] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[0].clone(),
-                            in_expr: expr.clone(),
-                        }));
+                func: |args, expr, _env| compare(args, expr, |ord| ord != Some(Ordering::Equal)),
+                name: "!=".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "zero?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(Value::Bool(
+                        num.partial_cmp(Num::Int(0)) == Some(Ordering::Equal),
+                    ))
+                },
+                name: "zero?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "positive?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(Value::Bool(
+                        num.partial_cmp(Num::Int(0)) == Some(Ordering::Greater),
+                    ))
+                },
+                name: "positive?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "negative?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(Value::Bool(
+                        num.partial_cmp(Num::Int(0)) == Some(Ordering::Less),
+                    ))
+                },
+                name: "negative?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "even?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let even = match Num::from_value(&args[0], &expr)? {
+                        Num::Int(n) => n % 2 == 0,
+                        Num::Float(n) if n.fract() == 0.0 => n % 2.0 == 0.0,
+                        Num::Float(_) | Num::Rational(..) => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "Integer".to_string(),
+                                found: args[0].clone(),
+                                in_expr: expr,
+                            }));
+                        }
                     };
-                    let second = if let Value::Number(val) = args[1] {
-                        val
-                    } else {
-                        return Err(Box::new(EvalError::TypeError {
-                            expected: "Number".to_string(),
-                            found: args[1].clone(),
-                            in_expr: expr.clone(),
-                        }));
+                    Ok(Value::Bool(even))
+                },
+                name: "even?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "odd?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let even = match Num::from_value(&args[0], &expr)? {
+                        Num::Int(n) => n % 2 == 0,
+                        Num::Float(n) if n.fract() == 0.0 => n % 2.0 == 0.0,
+                        Num::Float(_) | Num::Rational(..) => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "Integer".to_string(),
+                                found: args[0].clone(),
+                                in_expr: expr,
+                            }));
+                        }
                     };
-                    Ok(Value::Bool(first != second))
+                    Ok(Value::Bool(!even))
                 },
-                name: "!=".to_string(),
+                name: "odd?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
             }),
         );
+        self.define(
+            "abs",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let abs = match Num::from_value(&args[0], &expr)? {
+                        Num::Int(n) => n
+                            .checked_abs()
+                            .map_or(Num::Float((n as f64).abs()), Num::Int),
+                        Num::Rational(num, den) => num
+                            .checked_abs()
+                            .map_or(Num::Float((num as f64 / den as f64).abs()), |num| {
+                                Num::Rational(num, den)
+                            }),
+                        Num::Float(n) => Num::Float(n.abs()),
+                    };
+                    Ok(abs.into())
+                },
+                name: "abs".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "min",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| extremum(args, expr, Ordering::Less),
+                name: "min".to_string(),
+                min_args: 1,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "max",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| extremum(args, expr, Ordering::Greater),
+                name: "max".to_string(),
+                min_args: 1,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "square",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(num.mul(num).into())
+                },
+                name: "square".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "1+",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(num.add(Num::Int(1)).into())
+                },
+                name: "1+".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "add1",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(num.add(Num::Int(1)).into())
+                },
+                name: "add1".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "1-",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(num.sub(Num::Int(1)).into())
+                },
+                name: "1-".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "sub1",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(num.sub(Num::Int(1)).into())
+                },
+                name: "sub1".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "gcd",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let mut result = 0;
+                    for val in &args {
+                        result = gcd(result, integer_arg(val, &expr)?.unsigned_abs());
+                    }
+                    // only `(gcd i64::MIN)` and the like don't fit back
+                    i64::try_from(result).map(Value::Int).map_err(|_| {
+                        Box::new(EvalError::OtherError(format!("Integer overflow in {expr}")))
+                    })
+                },
+                name: "gcd".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "lcm",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let overflow =
+                        || Box::new(EvalError::OtherError(format!("Integer overflow in {expr}")));
+                    let mut result: u64 = 1;
+                    for val in &args {
+                        let n = integer_arg(val, &expr)?.unsigned_abs();
+                        if n == 0 {
+                            return Ok(Value::Int(0));
+                        }
+                        result = (result / gcd(result, n))
+                            .checked_mul(n)
+                            .ok_or_else(overflow)?;
+                    }
+                    i64::try_from(result)
+                        .map(Value::Int)
+                        .map_err(|_| overflow())
+                },
+                name: "lcm".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "bitwise-and",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| bitwise_fold(args, expr, -1, |a, b| a & b),
+                name: "bitwise-and".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "bitwise-or",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| bitwise_fold(args, expr, 0, |a, b| a | b),
+                name: "bitwise-or".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "bitwise-xor",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| bitwise_fold(args, expr, 0, |a, b| a ^ b),
+                name: "bitwise-xor".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "bitwise-not",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| Ok(Value::Int(!integer_arg(&args[0], &expr)?)),
+                name: "bitwise-not".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(arithmetic-shift n k)` shifts left by `k` bits, right when `k` is
+        // negative, keeping the sign like a multiplication by 2^k would
+        self.define(
+            "arithmetic-shift",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let n = integer_arg(&args[0], &expr)?;
+                    let shift = integer_arg(&args[1], &expr)?;
+                    if shift < 0 {
+                        return Ok(Value::Int(n >> shift.unsigned_abs().min(63)));
+                    }
+                    if n == 0 {
+                        return Ok(Value::Int(0));
+                    }
+                    u32::try_from(shift)
+                        .ok()
+                        .filter(|&shift| shift < 64)
+                        .and_then(|shift| i64::try_from((n as i128) << shift).ok())
+                        .map(Value::Int)
+                        .ok_or_else(|| {
+                            Box::new(EvalError::OtherError(format!("Integer overflow in {expr}")))
+                        })
+                },
+                name: "arithmetic-shift".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        // `sqrt` of a negative number is an error rather than NaN, there are
+        // no complex numbers to return. Perfect squares stay integers.
+        self.define(
+            "sqrt",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    if num.partial_cmp(Num::Int(0)) == Some(Ordering::Less) {
+                        return Err(Box::new(EvalError::OtherError(format!(
+                            "sqrt of negative number in {expr}"
+                        ))));
+                    }
+                    let root = num.as_f64().sqrt();
+                    if let Num::Int(n) = num
+                        && let root = root.round() as i64
+                        && root.checked_mul(root) == Some(n)
+                    {
+                        return Ok(Value::Int(root));
+                    }
+                    Ok(Value::Number(root))
+                },
+                name: "sqrt".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "expt",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let base = Num::from_value(&args[0], &expr)?;
+                    let power = Num::from_value(&args[1], &expr)?;
+                    if let (Some((num, den)), Num::Int(p)) = (base.parts(), power)
+                        && let Ok(p) = u32::try_from(p)
+                        && let (Some(num), Some(den)) = (num.checked_pow(p), den.checked_pow(p))
+                        && let Some(n) = Num::exact(num, den)
+                    {
+                        return Ok(n.into());
+                    }
+                    Ok(Value::Number(base.as_f64().powf(power.as_f64())))
+                },
+                name: "expt".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "exp",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    Ok(Value::Number(num.as_f64().exp()))
+                },
+                name: "exp".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(log x)` is the natural logarithm, `(log x b)` is in base `b`
+        self.define(
+            "log",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?.as_f64();
+                    if let Some(base) = args.get(1) {
+                        let base = Num::from_value(base, &expr)?.as_f64();
+                        return Ok(Value::Number(num.ln() / base.ln()));
+                    }
+                    Ok(Value::Number(num.ln()))
+                },
+                name: "log".to_string(),
+                min_args: 1,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "floor",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| round_with(args, expr, f64::floor),
+                name: "floor".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "ceiling",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| round_with(args, expr, f64::ceil),
+                name: "ceiling".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // rounds half to even like R7RS, so `(round 2.5)` is 2
+        self.define(
+            "round",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| round_with(args, expr, f64::round_ties_even),
+                name: "round".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "truncate",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| round_with(args, expr, f64::trunc),
+                name: "truncate".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "string-length",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::String(s) => Ok(Value::Int(s.chars().count() as i64)),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "String".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "string-length".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "string-append",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let mut result = String::new();
+                    for val in &args {
+                        match val {
+                            Value::String(s) => result.push_str(s),
+                            _ => {
+                                return Err(Box::new(EvalError::TypeError {
+                                    expected: "String".to_string(),
+                                    found: val.clone(),
+                                    in_expr: expr,
+                                }));
+                            }
+                        }
+                    }
+                    Ok(Value::String(result))
+                },
+                name: "string-append".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "substring",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let chars = match &args[0] {
+                        Value::String(s) => s.chars().collect::<Vec<char>>(),
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "String".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let start = index_arg(&args[1], &expr)?;
+                    let end = match args.get(2) {
+                        Some(end) => index_arg(end, &expr)?,
+                        None => chars.len(),
+                    };
+                    if start > end || end > chars.len() {
+                        return Err(Box::new(EvalError::OtherError(format!(
+                            "Index out of range: [{start}, {end}) of a string of length {} in {expr}",
+                            chars.len()
+                        ))));
+                    }
+                    Ok(Value::String(chars[start..end].iter().collect()))
+                },
+                name: "substring".to_string(),
+                min_args: 2,
+                max_args: Some(3),
+            }),
+        );
+        // strings are indexed by character, not by byte
+        self.define(
+            "string-ref",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let s = match &args[0] {
+                        Value::String(s) => s,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "String".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let k = index_arg(&args[1], &expr)?;
+                    s.chars().nth(k).map(Value::Char).ok_or_else(|| {
+                        Box::new(EvalError::OtherError(format!(
+                            "Index {k} out of range in {expr}"
+                        )))
+                    })
+                },
+                name: "string-ref".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        // `(string-replace-index s k c)` is `s` with its character at `k`
+        // replaced by `c`, strings being immutable there is no `string-set!`
+        self.define(
+            "string-replace-index",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let mut chars = match &args[0] {
+                        Value::String(s) => s.chars().collect::<Vec<char>>(),
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "String".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let k = index_arg(&args[1], &expr)?;
+                    let Value::Char(c) = args[2] else {
+                        return Err(Box::new(EvalError::TypeError {
+                            expected: "Char".to_string(),
+                            found: args[2].clone(),
+                            in_expr: expr,
+                        }));
+                    };
+                    match chars.get_mut(k) {
+                        Some(slot) => *slot = c,
+                        None => {
+                            return Err(Box::new(EvalError::OtherError(format!(
+                                "Index {k} out of range in {expr}"
+                            ))));
+                        }
+                    }
+                    Ok(Value::String(chars.into_iter().collect()))
+                },
+                name: "string-replace-index".to_string(),
+                min_args: 3,
+                max_args: Some(3),
+            }),
+        );
+        // string comparisons order by code point, the `-ci` ones compare
+        // the lowercased strings
+        self.define(
+            "string=?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_strings(args, expr, false, |ord| ord == Ordering::Equal)
+                },
+                name: "string=?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string<?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_strings(args, expr, false, |ord| ord == Ordering::Less)
+                },
+                name: "string<?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string>?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_strings(args, expr, false, |ord| ord == Ordering::Greater)
+                },
+                name: "string>?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string<=?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_strings(args, expr, false, |ord| ord != Ordering::Greater)
+                },
+                name: "string<=?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string>=?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_strings(args, expr, false, |ord| ord != Ordering::Less)
+                },
+                name: "string>=?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string-ci=?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_strings(args, expr, true, |ord| ord == Ordering::Equal)
+                },
+                name: "string-ci=?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string-ci<?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_strings(args, expr, true, |ord| ord == Ordering::Less)
+                },
+                name: "string-ci<?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string-ci>?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_strings(args, expr, true, |ord| ord == Ordering::Greater)
+                },
+                name: "string-ci>?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string-upcase",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::String(s) => Ok(Value::String(s.to_uppercase())),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "String".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "string-upcase".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "string-downcase",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::String(s) => Ok(Value::String(s.to_lowercase())),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "String".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "string-downcase".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(string-split s sep)` keeps empty fields between adjacent
+        // separators, but an empty string has no fields at all
+        self.define(
+            "string-split",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let [Value::String(s), Value::String(sep)] = args.as_slice() else {
+                        let found = args.iter().find(|val| !matches!(val, Value::String(_)));
+                        return Err(Box::new(EvalError::TypeError {
+                            expected: "String".to_string(),
+                            found: found.cloned().unwrap_or(Value::Unspecified),
+                            in_expr: expr,
+                        }));
+                    };
+                    if sep.is_empty() {
+                        return Err(Box::new(EvalError::OtherError(format!(
+                            "Empty separator in {expr}"
+                        ))));
+                    }
+                    if s.is_empty() {
+                        return Ok(Value::Nil);
+                    }
+                    Ok(Value::list(
+                        s.split(sep.as_str())
+                            .map(|field| Value::String(field.to_string()))
+                            .collect(),
+                    ))
+                },
+                name: "string-split".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        // `(string-join strings [sep])`, the separator defaulting to a space
+        self.define(
+            "string-join",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let sep = match args.get(1) {
+                        Some(Value::String(sep)) => sep.as_str(),
+                        Some(val) => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "String".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                        None => " ",
+                    };
+                    let items = args[0].list_to_vec().ok_or_else(|| {
+                        Box::new(EvalError::TypeError {
+                            expected: "List".to_string(),
+                            found: args[0].clone(),
+                            in_expr: expr.clone(),
+                        })
+                    })?;
+                    let strings = items
+                        .into_iter()
+                        .map(|val| match val {
+                            Value::String(s) => Ok(s),
+                            _ => Err(Box::new(EvalError::TypeError {
+                                expected: "String".to_string(),
+                                found: val,
+                                in_expr: expr.clone(),
+                            })),
+                        })
+                        .collect::<Result<Vec<String>, Box<EvalError>>>()?;
+                    Ok(Value::String(strings.join(sep)))
+                },
+                name: "string-join".to_string(),
+                min_args: 1,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "string->number",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let s = match &args[0] {
+                        Value::String(s) => s,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "String".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let radix = match args.get(1) {
+                        Some(radix) => radix_arg(radix, &expr)?,
+                        None => 10,
+                    };
+                    if radix != 10 {
+                        return Ok(
+                            i64::from_str_radix(s, radix).map_or(Value::Bool(false), Value::Int)
+                        );
+                    }
+                    match parse_number(s) {
+                        Some(Expr::Int(n)) => Ok(Value::Int(n)),
+                        Some(Expr::Number(n)) => Ok(Value::Number(n)),
+                        Some(Expr::Rational { num, den }) => Ok(Value::Rational { num, den }),
+                        _ => Ok(Value::Bool(false)),
+                    }
+                },
+                name: "string->number".to_string(),
+                min_args: 1,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "number->string",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let num = Num::from_value(&args[0], &expr)?;
+                    let radix = match args.get(1) {
+                        Some(radix) => radix_arg(radix, &expr)?,
+                        None => 10,
+                    };
+                    match num {
+                        Num::Int(n) => Ok(Value::String(format_radix(n, radix))),
+                        Num::Rational(num, den) => Ok(Value::String(format!(
+                            "{}/{}",
+                            format_radix(num, radix),
+                            format_radix(den, radix)
+                        ))),
+                        Num::Float(n) if radix == 10 => Ok(Value::String(format_float(n))),
+                        Num::Float(_) => Err(Box::new(EvalError::TypeError {
+                            expected: "Integer".to_string(),
+                            found: args[0].clone(),
+                            in_expr: expr,
+                        })),
+                    }
+                },
+                name: "number->string".to_string(),
+                min_args: 1,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "char->integer",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::Char(c) => Ok(Value::Int(*c as i64)),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "Char".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "char->integer".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "integer->char",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::Int(n) => u32::try_from(*n)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .map(Value::Char)
+                        .ok_or_else(|| {
+                            Box::new(EvalError::OtherError(format!(
+                                "{n} is not a valid Unicode scalar value in {expr}"
+                            )))
+                        }),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "Integer".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "integer->char".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        let char_classes: [(&str, BuiltinFn); 5] = [
+            ("char-alphabetic?", |args, expr, _env| {
+                char_class(args, expr, char::is_alphabetic)
+            }),
+            ("char-numeric?", |args, expr, _env| {
+                char_class(args, expr, char::is_numeric)
+            }),
+            ("char-whitespace?", |args, expr, _env| {
+                char_class(args, expr, char::is_whitespace)
+            }),
+            ("char-upper-case?", |args, expr, _env| {
+                char_class(args, expr, char::is_uppercase)
+            }),
+            ("char-lower-case?", |args, expr, _env| {
+                char_class(args, expr, char::is_lowercase)
+            }),
+        ];
+        for (name, func) in char_classes {
+            self.define(
+                name,
+                Value::BuiltinFunction(BuiltinFunc {
+                    func,
+                    name: name.to_string(),
+                    min_args: 1,
+                    max_args: Some(1),
+                }),
+            );
+        }
+        self.define(
+            "char=?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_all(args, expr, "Char", as_char, |ord| ord == Ordering::Equal)
+                },
+                name: "char=?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "char<?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    compare_all(args, expr, "Char", as_char, |ord| ord == Ordering::Less)
+                },
+                name: "char<?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "string->list",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::String(s) => Ok(Value::list(s.chars().map(Value::Char).collect())),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "String".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "string->list".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "list->string",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let items = args[0].list_to_vec().ok_or_else(|| {
+                        Box::new(EvalError::TypeError {
+                            expected: "List".to_string(),
+                            found: args[0].clone(),
+                            in_expr: expr.clone(),
+                        })
+                    })?;
+                    let mut result = String::new();
+                    for item in items {
+                        match item {
+                            Value::Char(c) => result.push(c),
+                            val => {
+                                return Err(Box::new(EvalError::TypeError {
+                                    expected: "Char".to_string(),
+                                    found: val,
+                                    in_expr: expr,
+                                }));
+                            }
+                        }
+                    }
+                    Ok(Value::String(result))
+                },
+                name: "list->string".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "vector",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Vector(Rc::new(RefCell::new(args)))),
+                name: "vector".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "make-vector",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let size = index_arg(&args[0], &expr)?;
+                    let fill = args.get(1).cloned().unwrap_or(Value::Int(0));
+                    let mut items = alloc_values(size, &expr)?;
+                    items.resize(size, fill);
+                    Ok(Value::Vector(Rc::new(RefCell::new(items))))
+                },
+                name: "make-vector".to_string(),
+                min_args: 1,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "vector?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(matches!(args[0], Value::Vector(_)))),
+                name: "vector?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "vector-length",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let vector = match &args[0] {
+                        Value::Vector(vector) => vector,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "Vector".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    Ok(Value::Int(vector.borrow().len() as i64))
+                },
+                name: "vector-length".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "vector-ref",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let vector = match &args[0] {
+                        Value::Vector(vector) => vector,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "Vector".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let k = index_arg(&args[1], &expr)?;
+                    let val = vector.borrow().get(k).cloned();
+                    val.ok_or_else(|| {
+                        Box::new(EvalError::OtherError(format!(
+                            "Index {k} out of range in {expr}"
+                        )))
+                    })
+                },
+                name: "vector-ref".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "vector-set!",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let vector = match &args[0] {
+                        Value::Vector(vector) => vector,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "Vector".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let k = index_arg(&args[1], &expr)?;
+                    match vector.borrow_mut().get_mut(k) {
+                        Some(slot) => *slot = args[2].clone(),
+                        None => {
+                            return Err(Box::new(EvalError::OtherError(format!(
+                                "Index {k} out of range in {expr}"
+                            ))));
+                        }
+                    }
+                    Ok(Value::Unspecified)
+                },
+                name: "vector-set!".to_string(),
+                min_args: 3,
+                max_args: Some(3),
+            }),
+        );
+        self.define(
+            "make-hash-table",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |_args, _expr, _env| {
+                    Ok(Value::HashTable(Rc::new(RefCell::new(HashMap::new()))))
+                },
+                name: "make-hash-table".to_string(),
+                min_args: 0,
+                max_args: Some(0),
+            }),
+        );
+        self.define(
+            "hash-table-set!",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let table = match &args[0] {
+                        Value::HashTable(table) => table,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "HashTable".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    table
+                        .borrow_mut()
+                        .insert(hash_key(&args[1], &expr)?, args[2].clone());
+                    Ok(Value::Unspecified)
+                },
+                name: "hash-table-set!".to_string(),
+                min_args: 3,
+                max_args: Some(3),
+            }),
+        );
+        self.define(
+            "hash-table-ref",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let table = match &args[0] {
+                        Value::HashTable(table) => table,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "HashTable".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let found = table.borrow().get(&hash_key(&args[1], &expr)?).cloned();
+                    match (found, args.get(2)) {
+                        (Some(val), _) => Ok(val),
+                        (None, Some(default)) => Ok(default.clone()),
+                        (None, None) => Err(Box::new(EvalError::OtherError(format!(
+                            "Key {} not found in {expr}",
+                            args[1]
+                        )))),
+                    }
+                },
+                name: "hash-table-ref".to_string(),
+                min_args: 2,
+                max_args: Some(3),
+            }),
+        );
+        self.define(
+            "hash-table-delete!",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let table = match &args[0] {
+                        Value::HashTable(table) => table,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "HashTable".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    table.borrow_mut().remove(&hash_key(&args[1], &expr)?);
+                    Ok(Value::Unspecified)
+                },
+                name: "hash-table-delete!".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "hash-table-keys",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let table = match &args[0] {
+                        Value::HashTable(table) => table,
+                        val => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "HashTable".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let keys = table.borrow().keys().map(|key| key.0.clone()).collect();
+                    Ok(Value::list(keys))
+                },
+                name: "hash-table-keys".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "load",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| {
+                    let path = if let Value::String(path) = &args[0] {
+                        path
+                    } else {
+                        return Err(Box::new(EvalError::TypeError {
+                            expected: "String".to_string(),
+                            found: args[0].clone(),
+                            in_expr: expr.clone(),
+                        }));
+                    };
+                    let file_error = |desc| {
+                        Box::new(EvalError::FileError {
+                            path: path.clone(),
+                            desc,
+                        })
+                    };
+                    let file = File::open(path).map_err(|e| file_error(e.to_string()))?;
+                    match env.load_tokens(Tokenizer::from_reader(file)) {
+                        Err(e) => match *e {
+                            EvalError::ParseError(ParseError::Io(_, desc)) => Err(file_error(desc)),
+                            e => Err(Box::new(e)),
+                        },
+                        Ok(()) => Ok(Value::Unspecified),
+                    }
+                },
+                name: "load".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(force promise)` evaluates a `delay`ed expression the first time
+        // and returns the remembered value after that. Anything that isn't a
+        // promise is returned as is.
+        self.define(
+            "force",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| {
+                    let Value::Promise(promise) = &args[0] else {
+                        return Ok(args[0].clone());
+                    };
+                    let (delayed, mut delayed_env) = match &*promise.borrow() {
+                        Promise::Forced(val) => return Ok(val.clone()),
+                        Promise::Delayed { expr, env } => (expr.clone(), env.borrow().clone()),
+                    };
+                    let val = eval(&delayed, &mut delayed_env)?;
+                    // forcing the promise from within its own expression may
+                    // have settled it already, the first value wins
+                    let mut promise = promise.borrow_mut();
+                    if let Promise::Forced(val) = &*promise {
+                        return Ok(val.clone());
+                    }
+                    *promise = Promise::Forced(val.clone());
+                    Ok(val)
+                },
+                name: "force".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // Continuations are escape-only: calling one unwinds `eval` back to
+        // the `call/cc` that made it, which returns the passed value. They
+        // can't be resumed once that `call/cc` has returned.
+        for name in ["call/cc", "call-with-current-continuation"] {
+            self.define(
+                name,
+                Value::BuiltinFunction(BuiltinFunc {
+                    func: |args, expr, env| {
+                        let id = CONTINUATION_COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+                        let k = Value::Continuation(Continuation { id });
+                        match apply(args[0].clone(), vec![k], &expr, env) {
+                            Err(e) => match *e {
+                                EvalError::Escape { id: escaped, value } if escaped == id => {
+                                    Ok(value)
+                                }
+                                e => Err(Box::new(e)),
+                            },
+                            result => result,
+                        }
+                    },
+                    name: name.to_string(),
+                    min_args: 1,
+                    max_args: Some(1),
+                }),
+            );
+        }
+        self.define(
+            "make-parameter",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| {
+                    Ok(Value::Parameter(Rc::new(RefCell::new(args[0].clone()))))
+                },
+                name: "make-parameter".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // a single value is returned as is, so `values` only stands out
+        // where `call-with-values` takes it apart
+        self.define(
+            "values",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |mut args, _expr, _env| {
+                    if args.len() == 1 {
+                        return Ok(args.remove(0));
+                    }
+                    Ok(Value::Values(args))
+                },
+                name: "values".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "call-with-values",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| {
+                    let values = match apply(args[0].clone(), Vec::new(), &expr, env)? {
+                        Value::Values(values) => values,
+                        value => vec![value],
+                    };
+                    apply(args[1].clone(), values, &expr, env)
+                },
+                name: "call-with-values".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        // `(read)` parses the next datum from standard input, `(read s)` the
+        // first one in the string `s`. Either returns the eof object when
+        // there is no datum left. Standard input is read a line at a time,
+        // so anything after the datum on its last line is dropped.
+        self.define(
+            "read",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let source = match args.as_slice() {
+                        [] => {
+                            let mut input = String::new();
+                            loop {
+                                let read = std::io::stdin().read_line(&mut input).map_err(|e| {
+                                    Box::new(EvalError::FileError {
+                                        path: "<stdin>".to_string(),
+                                        desc: e.to_string(),
+                                    })
+                                })?;
+                                // blank and comment-only lines hold no datum, only
+                                // the end of input is the end of file
+                                let has_datum = Tokenizer::new(&input).next().is_some();
+                                if read == 0 || has_datum && balance(&input) == Balance::Complete {
+                                    break;
+                                }
+                            }
+                            input
+                        }
+                        [Value::String(s), ..] => s.clone(),
+                        [val, ..] => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "String".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let mut tokens = Tokenizer::new(&source).peekable();
+                    if tokens.peek().is_none() {
+                        return Ok(Value::Eof);
+                    }
+                    match parse(&mut tokens) {
+                        Ok(datum) => Ok(quote(&datum)),
+                        Err(e) => Err(Box::new(EvalError::ParseError(e))),
+                    }
+                },
+                name: "read".to_string(),
+                min_args: 0,
+                max_args: Some(1),
+            }),
+        );
+        // `(eval datum)` evaluates data as code, in the environment of the call
+        self.define(
+            "eval",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, env| eval(&datum_to_expr(&args[0])?, env),
+                name: "eval".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(environment-bindings [include-builtins])`, the variables of the
+        // calling frame as an alist sorted by name. Builtin procedures are
+        // left out unless `include-builtins` is true.
+        self.define(
+            "environment-bindings",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, env| {
+                    let include_builtins = !matches!(args.first(), None | Some(Value::Bool(false)));
+                    let vars = env.vars.borrow();
+                    let mut bindings: Vec<(&Symbol, &Value)> = vars
+                        .iter()
+                        .filter(|(_, val)| {
+                            include_builtins || !matches!(val, Value::BuiltinFunction(_))
+                        })
+                        .collect();
+                    bindings.sort_by_key(|(name, _)| name.as_str());
+                    Ok(Value::list(
+                        bindings
+                            .into_iter()
+                            .map(|(name, val)| {
+                                Value::cons(Value::Symbol(name.to_string()), val.clone())
+                            })
+                            .collect(),
+                    ))
+                },
+                name: "environment-bindings".to_string(),
+                min_args: 0,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "eof-object",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |_args, _expr, _env| Ok(Value::Eof),
+                name: "eof-object".to_string(),
+                min_args: 0,
+                max_args: Some(0),
+            }),
+        );
+        self.define(
+            "eof-object?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(matches!(args[0], Value::Eof))),
+                name: "eof-object?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "display",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| {
+                    match &args[0] {
+                        Value::String(s) => print!("{s}"),
+                        Value::Char(c) => print!("{c}"),
+                        val => print!("{val}"),
+                    }
+                    Ok(Value::Unspecified)
+                },
+                name: "display".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "newline",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |_args, _expr, _env| {
+                    println!();
+                    Ok(Value::Unspecified)
+                },
+                name: "newline".to_string(),
+                min_args: 0,
+                max_args: Some(0),
+            }),
+        );
+        self.define(
+            "error",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::String(message) => Err(Box::new(EvalError::UserError {
+                        message: message.clone(),
+                        irritants: args[1..].to_vec(),
+                    })),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "String".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "error".to_string(),
+                min_args: 1,
+                max_args: None,
+            }),
+        );
+        // `(raise obj)` signals `obj` to the nearest exception handler.
+        // Raising a caught condition rethrows the original error.
+        self.define(
+            "raise",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| {
+                    let condition = args.into_iter().next().unwrap();
+                    Err(Box::new(EvalError::from_condition(condition)))
+                },
+                name: "raise".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(with-exception-handler handler thunk)` calls `thunk`, and if it
+        // raises or fails calls `handler` with the condition instead, whose
+        // result becomes the result. Unlike R7RS the handler runs after the
+        // thunk has been unwound, like a `guard`.
+        self.define(
+            "with-exception-handler",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| {
+                    if let Some(val) = args.iter().find(|val| !val.is_procedure()) {
+                        return Err(Box::new(EvalError::TypeError {
+                            expected: "function".to_string(),
+                            found: val.clone(),
+                            in_expr: expr,
+                        }));
+                    }
+                    match apply(args[1].clone(), Vec::new(), &expr, env) {
+                        // continuations, timeouts and running out of fuel escape
+                        // through handlers
+                        Err(e) if e.is_catchable() => {
+                            apply(args[0].clone(), vec![(*e).into_condition()], &expr, env)
+                        }
+                        result => result,
+                    }
+                },
+                name: "with-exception-handler".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "error-object?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(matches!(args[0], Value::Condition(_)))),
+                name: "error-object?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // the message given to `error`, or the description of any other error
+        self.define(
+            "error-object-message",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::Condition(e) => match &**e {
+                        EvalError::UserError { message, .. } => Ok(Value::String(message.clone())),
+                        e => Ok(Value::String(e.to_string())),
+                    },
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "Condition".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "error-object-message".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "error-object-irritants",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::Condition(e) => match &**e {
+                        EvalError::UserError { irritants, .. } => {
+                            Ok(Value::list(irritants.clone()))
+                        }
+                        _ => Ok(Value::Nil),
+                    },
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "Condition".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "error-object-irritants".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "cons",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| {
+                    let mut args = args.into_iter();
+                    let car = args.next().unwrap();
+                    let cdr = args.next().unwrap();
+                    Ok(Value::cons(car, cdr))
+                },
+                name: "cons".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "car",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::Pair(pair) => Ok(pair.0.clone()),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "Pair".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "car".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "cdr",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::Pair(pair) => Ok(pair.1.clone()),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "Pair".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "cdr".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // compositions of `car` and `cdr`, `(cadr x)` being `(car (cdr x))`
+        let accessors: [(&str, BuiltinFn); 17] = [
+            ("first", |args, expr, _env| cxr(args, expr, "a")),
+            ("second", |args, expr, _env| cxr(args, expr, "ad")),
+            ("third", |args, expr, _env| cxr(args, expr, "add")),
+            ("fourth", |args, expr, _env| cxr(args, expr, "addd")),
+            ("fifth", |args, expr, _env| cxr(args, expr, "adddd")),
+            ("caar", |args, expr, _env| cxr(args, expr, "aa")),
+            ("cadr", |args, expr, _env| cxr(args, expr, "ad")),
+            ("cdar", |args, expr, _env| cxr(args, expr, "da")),
+            ("cddr", |args, expr, _env| cxr(args, expr, "dd")),
+            ("caaar", |args, expr, _env| cxr(args, expr, "aaa")),
+            ("caadr", |args, expr, _env| cxr(args, expr, "aad")),
+            ("cadar", |args, expr, _env| cxr(args, expr, "ada")),
+            ("caddr", |args, expr, _env| cxr(args, expr, "add")),
+            ("cdaar", |args, expr, _env| cxr(args, expr, "daa")),
+            ("cdadr", |args, expr, _env| cxr(args, expr, "dad")),
+            ("cddar", |args, expr, _env| cxr(args, expr, "dda")),
+            ("cdddr", |args, expr, _env| cxr(args, expr, "ddd")),
+        ];
+        for (name, func) in accessors {
+            self.define(
+                name,
+                Value::BuiltinFunction(BuiltinFunc {
+                    func,
+                    name: name.to_string(),
+                    min_args: 1,
+                    max_args: Some(1),
+                }),
+            );
+        }
+        self.define(
+            "list",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::list(args)),
+                name: "list".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        // `(iota count [start [step]])` counts up from `start` (default 0)
+        // in increments of `step` (default 1)
+        self.define(
+            "iota",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let count = index_arg(&args[0], &expr)?;
+                    let start = match args.get(1) {
+                        Some(val) => Num::from_value(val, &expr)?,
+                        None => Num::Int(0),
+                    };
+                    let step = match args.get(2) {
+                        Some(val) => Num::from_value(val, &expr)?,
+                        None => Num::Int(1),
+                    };
+                    // each element is computed from its index so float steps
+                    // don't accumulate rounding errors
+                    let mut items = alloc_values(count, &expr)?;
+                    items.extend(
+                        (0..count).map(|i| Value::from(start.add(Num::Int(i as i64).mul(step)))),
+                    );
+                    Ok(Value::list(items))
+                },
+                name: "iota".to_string(),
+                min_args: 1,
+                max_args: Some(3),
+            }),
+        );
+        self.define(
+            "null?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(matches!(args[0], Value::Nil))),
+                name: "null?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "pair?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(matches!(args[0], Value::Pair(_)))),
+                name: "pair?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "reverse",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let items = args[0].list_to_vec().ok_or_else(|| {
+                        Box::new(EvalError::TypeError {
+                            expected: "List".to_string(),
+                            found: args[0].clone(),
+                            in_expr: expr.clone(),
+                        })
+                    })?;
+                    Ok(items
+                        .into_iter()
+                        .fold(Value::Nil, |tail, head| Value::cons(head, tail)))
+                },
+                name: "reverse".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "append",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |mut args, expr, _env| {
+                    // the last argument is shared, not copied, and may be any value
+                    let mut result = args.pop().unwrap_or(Value::Nil);
+                    for list in args.iter().rev() {
+                        let items = list.list_to_vec().ok_or_else(|| {
+                            Box::new(EvalError::TypeError {
+                                expected: "List".to_string(),
+                                found: list.clone(),
+                                in_expr: expr.clone(),
+                            })
+                        })?;
+                        for item in items.into_iter().rev() {
+                            result = Value::cons(item, result);
+                        }
+                    }
+                    Ok(result)
+                },
+                name: "append".to_string(),
+                min_args: 0,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "list-tail",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let k = index_arg(&args[1], &expr)?;
+                    list_tail(&args[0], k, &expr)
+                },
+                name: "list-tail".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "list-ref",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let k = index_arg(&args[1], &expr)?;
+                    match list_tail(&args[0], k, &expr)? {
+                        Value::Pair(pair) => Ok(pair.0.clone()),
+                        _ => Err(Box::new(EvalError::OtherError(format!(
+                            "Index {k} out of range in {expr}"
+                        )))),
+                    }
+                },
+                name: "list-ref".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "take",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let k = index_arg(&args[1], &expr)?;
+                    let mut items = Vec::new();
+                    let mut cur = &args[0];
+                    for _ in 0..k {
+                        match cur {
+                            Value::Pair(pair) => {
+                                items.push(pair.0.clone());
+                                cur = &pair.1;
+                            }
+                            _ => {
+                                return Err(Box::new(EvalError::OtherError(format!(
+                                    "Index {k} out of range in {expr}"
+                                ))));
+                            }
+                        }
+                    }
+                    Ok(Value::list(items))
+                },
+                name: "take".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "drop",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let k = index_arg(&args[1], &expr)?;
+                    list_tail(&args[0], k, &expr)
+                },
+                name: "drop".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "last",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let Value::Pair(mut pair) = args[0].clone() else {
+                        return Err(Box::new(EvalError::TypeError {
+                            expected: "Pair".to_string(),
+                            found: args[0].clone(),
+                            in_expr: expr,
+                        }));
+                    };
+                    while let Value::Pair(next) = &pair.1 {
+                        pair = next.clone();
+                    }
+                    Ok(pair.0.clone())
+                },
+                name: "last".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "memq",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| member_with(args, expr, Value::is_eq),
+                name: "memq".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "memv",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| member_with(args, expr, Value::is_eqv),
+                name: "memv".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "member",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| member_with(args, expr, Value::is_equal),
+                name: "member".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "assq",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| assoc_with(args, expr, Value::is_eq),
+                name: "assq".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "assv",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| assoc_with(args, expr, Value::is_eqv),
+                name: "assv".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "assoc",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| assoc_with(args, expr, Value::is_equal),
+                name: "assoc".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "alist-cons",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| {
+                    let entry = Value::cons(args[0].clone(), args[1].clone());
+                    Ok(Value::cons(entry, args[2].clone()))
+                },
+                name: "alist-cons".to_string(),
+                min_args: 3,
+                max_args: Some(3),
+            }),
+        );
+        // a new list without the entries whose key is `equal?` to the one
+        // given, the original is left as it was
+        for name in ["alist-delete", "del-assoc"] {
+            self.define(
+                name,
+                Value::BuiltinFunction(BuiltinFunc {
+                    func: |args, expr, _env| {
+                        let entries = args[1].list_to_vec().ok_or_else(|| {
+                            Box::new(EvalError::TypeError {
+                                expected: "List".to_string(),
+                                found: args[1].clone(),
+                                in_expr: expr.clone(),
+                            })
+                        })?;
+                        let mut kept = Vec::new();
+                        for entry in entries {
+                            match &entry {
+                                Value::Pair(pair) if pair.0.is_equal(&args[0]) => {}
+                                Value::Pair(_) => kept.push(entry),
+                                _ => {
+                                    return Err(Box::new(EvalError::TypeError {
+                                        expected: "Pair".to_string(),
+                                        found: entry,
+                                        in_expr: expr,
+                                    }));
+                                }
+                            }
+                        }
+                        Ok(Value::list(kept))
+                    },
+                    name: name.to_string(),
+                    min_args: 2,
+                    max_args: Some(2),
+                }),
+            );
+        }
+        self.define(
+            "for-each",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| {
+                    let (lists, len) = list_args(&args[1..], &expr)?;
+                    for i in 0..len {
+                        let call_args = lists.iter().map(|list| list[i].clone()).collect();
+                        apply(args[0].clone(), call_args, &expr, env)?;
+                    }
+                    Ok(Value::Unspecified)
+                },
+                name: "for-each".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        // `(count pred list...)`, how many times `pred` held, the lists
+        // being walked in step like `for-each`
+        self.define(
+            "count",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| {
+                    let (lists, len) = list_args(&args[1..], &expr)?;
+                    let mut count = 0;
+                    for i in 0..len {
+                        let call_args = lists.iter().map(|list| list[i].clone()).collect();
+                        match apply(args[0].clone(), call_args, &expr, env)? {
+                            Value::Bool(true) => count += 1,
+                            Value::Bool(false) => {}
+                            found => {
+                                return Err(Box::new(EvalError::TypeError {
+                                    expected: "Bool".to_string(),
+                                    found,
+                                    in_expr: expr,
+                                }));
+                            }
+                        }
+                    }
+                    Ok(Value::Int(count))
+                },
+                name: "count".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        // `(reduce f default list)` folds the list with its first element as
+        // the seed, calling `(f item acc)`; `default` is the result for an
+        // empty list. `reduce-right` folds from the end, `(f item acc)` with
+        // the last element as the seed.
+        self.define(
+            "reduce",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| reduce_with(args, expr, env, false),
+                name: "reduce".to_string(),
+                min_args: 3,
+                max_args: Some(3),
+            }),
+        );
+        self.define(
+            "reduce-right",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| reduce_with(args, expr, env, true),
+                name: "reduce-right".to_string(),
+                min_args: 3,
+                max_args: Some(3),
+            }),
+        );
+        // `(zip list...)`, a list of the lists' first elements, then their
+        // second ones, and so on. Like `for-each` it stops at the end of the
+        // shortest list.
+        self.define(
+            "zip",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let (lists, len) = list_args(&args, &expr)?;
+                    let tuples = (0..len)
+                        .map(|i| Value::list(lists.iter().map(|list| list[i].clone()).collect()))
+                        .collect();
+                    Ok(Value::list(tuples))
+                },
+                name: "zip".to_string(),
+                min_args: 1,
+                max_args: None,
+            }),
+        );
+        // `(unzip2 list)` undoes a two-list `zip`, returning the first and
+        // the second elements of each item as two values
+        self.define(
+            "unzip2",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let (lists, _) = list_args(&args, &expr)?;
+                    let (mut firsts, mut seconds) = (Vec::new(), Vec::new());
+                    for item in &lists[0] {
+                        match item.list_to_vec().as_deref() {
+                            Some([first, second, ..]) => {
+                                firsts.push(first.clone());
+                                seconds.push(second.clone());
+                            }
+                            _ => {
+                                return Err(Box::new(EvalError::TypeError {
+                                    expected: "List of at least 2 elements".to_string(),
+                                    found: item.clone(),
+                                    in_expr: expr,
+                                }));
+                            }
+                        }
+                    }
+                    Ok(Value::Values(vec![
+                        Value::list(firsts),
+                        Value::list(seconds),
+                    ]))
+                },
+                name: "unzip2".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(flatten tree)`, the atoms of a nested list in order. Empty lists
+        // vanish, and the tail of an improper list is kept as its last atom,
+        // so `(flatten '(1 (2 . 3)))` is `(1 2 3)`.
+        self.define(
+            "flatten",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    if !matches!(args[0], Value::Pair(_) | Value::Nil) {
+                        return Err(Box::new(EvalError::TypeError {
+                            expected: "List".to_string(),
+                            found: args[0].clone(),
+                            in_expr: expr,
+                        }));
+                    }
+                    // walked with a stack of what is left, however deep the nesting
+                    let mut atoms = Vec::new();
+                    let mut pending = vec![args[0].clone()];
+                    while let Some(val) = pending.pop() {
+                        match val {
+                            Value::Pair(pair) => pending.extend([pair.1.clone(), pair.0.clone()]),
+                            Value::Nil => {}
+                            atom => atoms.push(atom),
+                        }
+                    }
+                    Ok(Value::list(atoms))
+                },
+                name: "flatten".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(sort list less?)` returns a new list, keeping the original order
+        // of elements that compare equal
+        self.define(
+            "sort",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| {
+                    let items = args[0].list_to_vec().ok_or_else(|| {
+                        Box::new(EvalError::TypeError {
+                            expected: "List".to_string(),
+                            found: args[0].clone(),
+                            in_expr: expr.clone(),
+                        })
+                    })?;
+                    let less = &args[1];
+                    if !less.is_procedure() {
+                        return Err(Box::new(EvalError::TypeError {
+                            expected: "function".to_string(),
+                            found: less.clone(),
+                            in_expr: expr,
+                        }));
+                    }
+                    let sorted = merge_sort(items, &mut |a, b| match apply(
+                        less.clone(),
+                        vec![a.clone(), b.clone()],
+                        &expr,
+                        env,
+                    )? {
+                        Value::Bool(b) => Ok(b),
+                        found => Err(Box::new(EvalError::TypeError {
+                            expected: "Bool".to_string(),
+                            found,
+                            in_expr: expr.clone(),
+                        })),
+                    })?;
+                    Ok(Value::list(sorted))
+                },
+                name: "sort".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        // `(sort-by key less? list)` sorts like `sort`, comparing `(key item)`
+        // instead of the items, with `key` called once per item
+        self.define(
+            "sort-by",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| {
+                    let (key, less) = (&args[0], &args[1]);
+                    for proc in [key, less] {
+                        if !proc.is_procedure() {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "function".to_string(),
+                                found: proc.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    }
+                    let items = args[2].list_to_vec().ok_or_else(|| {
+                        Box::new(EvalError::TypeError {
+                            expected: "List".to_string(),
+                            found: args[2].clone(),
+                            in_expr: expr.clone(),
+                        })
+                    })?;
+                    let keyed = items
+                        .into_iter()
+                        .map(|item| Ok((apply(key.clone(), vec![item.clone()], &expr, env)?, item)))
+                        .collect::<Result<Vec<_>, Box<EvalError>>>()?;
+                    let sorted = merge_sort(keyed, &mut |(a, _), (b, _)| match apply(
+                        less.clone(),
+                        vec![a.clone(), b.clone()],
+                        &expr,
+                        env,
+                    )? {
+                        Value::Bool(b) => Ok(b),
+                        found => Err(Box::new(EvalError::TypeError {
+                            expected: "Bool".to_string(),
+                            found,
+                            in_expr: expr.clone(),
+                        })),
+                    })?;
+                    Ok(Value::list(
+                        sorted.into_iter().map(|(_, item)| item).collect(),
+                    ))
+                },
+                name: "sort-by".to_string(),
+                min_args: 3,
+                max_args: Some(3),
+            }),
+        );
+        self.define(
+            "number?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| {
+                    Ok(Value::Bool(matches!(
+                        args[0],
+                        Value::Int(_) | Value::Rational { .. } | Value::Number(_)
+                    )))
+                },
+                name: "number?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // integers are the exact numbers, floats the inexact ones
+        self.define(
+            "exact?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match number_arg(args, expr)? {
+                    Value::Int(_) | Value::Rational { .. } => Ok(Value::Bool(true)),
+                    _ => Ok(Value::Bool(false)),
+                },
+                name: "exact?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "inexact?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match number_arg(args, expr)? {
+                    Value::Number(_) => Ok(Value::Bool(true)),
+                    _ => Ok(Value::Bool(false)),
+                },
+                name: "inexact?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        for name in ["exact->inexact", "inexact"] {
+            self.define(
+                name,
+                Value::BuiltinFunction(BuiltinFunc {
+                    func: |args, expr, _env| match number_arg(args, expr)? {
+                        Value::Int(n) => Ok(Value::Number(n as f64)),
+                        Value::Rational { num, den } => Ok(Value::Number(num as f64 / den as f64)),
+                        val => Ok(val),
+                    },
+                    name: name.to_string(),
+                    min_args: 1,
+                    max_args: Some(1),
+                }),
+            );
+        }
+        // a finite float is a fraction over a power of two, and is exact
+        // whenever both parts fit
+        for name in ["inexact->exact", "exact"] {
+            self.define(
+                name,
+                Value::BuiltinFunction(BuiltinFunc {
+                    func: |args, expr, _env| match number_arg(args, expr.clone())? {
+                        Value::Number(n) => {
+                            let (mut num, mut den) = (n, 1_i128);
+                            while num.fract() != 0.0 && den < 1 << 62 {
+                                num *= 2.0;
+                                den *= 2;
+                            }
+                            let exact = (num.fract() == 0.0 && num.abs() < i64::MAX as f64)
+                                .then(|| Num::exact(num as i128, den))
+                                .flatten();
+                            exact.map(Value::from).ok_or_else(|| {
+                                Box::new(EvalError::OtherError(format!(
+                                    "{} has no exact representation in {expr}",
+                                    format_float(n)
+                                )))
+                            })
+                        }
+                        val => Ok(val),
+                    },
+                    name: name.to_string(),
+                    min_args: 1,
+                    max_args: Some(1),
+                }),
+            );
+        }
+        self.define(
+            "boolean?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(matches!(args[0], Value::Bool(_)))),
+                name: "boolean?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "boolean=?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let get = |val: &Value| match val {
+                        Value::Bool(b) => Some(*b),
+                        _ => None,
+                    };
+                    compare_all(args, expr, "Bool", get, |ord| ord == Ordering::Equal)
+                },
+                name: "boolean=?".to_string(),
+                min_args: 2,
+                max_args: None,
+            }),
+        );
+        self.define(
+            "procedure?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(args[0].is_procedure())),
+                name: "procedure?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        // `(defined? 'name)`, whether `name` is bound where the call is made
+        self.define(
+            "defined?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, env| match &args[0] {
+                    Value::Symbol(name) => Ok(Value::Bool(env.is_bound(name))),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "Symbol".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "defined?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "string?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(matches!(args[0], Value::String(_)))),
+                name: "string?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "symbol?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(matches!(args[0], Value::Symbol(_)))),
+                name: "symbol?".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "eq?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(args[0].is_eq(&args[1]))),
+                name: "eq?".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "eqv?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(args[0].is_eqv(&args[1]))),
+                name: "eqv?".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "equal?",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, _expr, _env| Ok(Value::Bool(args[0].is_equal(&args[1]))),
+                name: "equal?".to_string(),
+                min_args: 2,
+                max_args: Some(2),
+            }),
+        );
+        self.define(
+            "symbol->string",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::Symbol(s) => Ok(Value::String(s.clone())),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "Symbol".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "symbol->string".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "string->symbol",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| match &args[0] {
+                    Value::String(s) => Ok(Value::Symbol(s.clone())),
+                    val => Err(Box::new(EvalError::TypeError {
+                        expected: "String".to_string(),
+                        found: val.clone(),
+                        in_expr: expr,
+                    })),
+                },
+                name: "string->symbol".to_string(),
+                min_args: 1,
+                max_args: Some(1),
+            }),
+        );
+        self.define(
+            "gensym",
+            Value::BuiltinFunction(BuiltinFunc {
+                func: |args, expr, _env| {
+                    let prefix = match args.as_slice() {
+                        [] => "g",
+                        [Value::String(prefix), ..] => prefix,
+                        [val, ..] => {
+                            return Err(Box::new(EvalError::TypeError {
+                                expected: "String".to_string(),
+                                found: val.clone(),
+                                in_expr: expr,
+                            }));
+                        }
+                    };
+                    let id = GENSYM_COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+                    Ok(Value::Symbol(format!("#:{prefix}{id}")))
+                },
+                name: "gensym".to_string(),
+                min_args: 0,
+                max_args: Some(1),
+            }),
+        );
+    }
+}
+
+/// Numerator and denominator, wide enough to combine two `i64` fractions
+/// without overflowing.
+type Ratio = (i128, i128);
+
+/// A numeric operand. Integer and rational arithmetic stays exact and only
+/// becomes floating-point once a float is involved (or an exact op
+/// overflows).
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    Int(i64),
+    /// In lowest terms, the denominator greater than 1.
+    Rational(i64, i64),
+    Float(f64),
+}
+
+impl Num {
+    fn from_value(val: &Value, expr: &Expr) -> Result<Num, Box<EvalError>> {
+        match val {
+            Value::Int(n) => Ok(Num::Int(*n)),
+            Value::Rational { num, den } => Ok(Num::Rational(*num, *den)),
+            Value::Number(n) => Ok(Num::Float(*n)),
+            _ => Err(Box::new(EvalError::TypeError {
+                expected: "Number".to_string(),
+                found: val.clone(),
+                in_expr: expr.clone(),
+            })),
+        }
+    }
+
+    /// The exact number `num/den`, or `None` if it doesn't fit.
+    fn exact(num: i128, den: i128) -> Option<Num> {
+        match reduce_ratio(num, den)? {
+            (n, 1) => Some(Num::Int(n)),
+            (num, den) => Some(Num::Rational(num, den)),
+        }
+    }
+
+    /// Numerator and denominator of an exact number.
+    fn parts(self) -> Option<Ratio> {
+        match self {
+            Num::Int(n) => Some((n.into(), 1)),
+            Num::Rational(num, den) => Some((num.into(), den.into())),
+            Num::Float(_) => None,
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Num::Int(n) => n as f64,
+            Num::Rational(num, den) => num as f64 / den as f64,
+            Num::Float(n) => n,
+        }
+    }
+
+    /// Apply `exact_op` to the numerators and denominators of exact
+    /// operands, `float_op` otherwise or when the exact result overflows.
+    fn arith(
+        self,
+        other: Num,
+        exact_op: fn(Ratio, Ratio) -> Option<Ratio>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Num {
+        if let (Some(a), Some(b)) = (self.parts(), other.parts())
+            && let Some(num) = exact_op(a, b).and_then(|(num, den)| Num::exact(num, den))
+        {
+            return num;
+        }
+        Num::Float(float_op(self.as_f64(), other.as_f64()))
+    }
+
+    fn add(self, other: Num) -> Num {
+        self.arith(
+            other,
+            |(a, b), (c, d)| Some((a.checked_mul(d)?.checked_add(c.checked_mul(b)?)?, b * d)),
+            |a, b| a + b,
+        )
+    }
+
+    fn sub(self, other: Num) -> Num {
+        self.arith(
+            other,
+            |(a, b), (c, d)| Some((a.checked_mul(d)?.checked_sub(c.checked_mul(b)?)?, b * d)),
+            |a, b| a - b,
+        )
+    }
+
+    fn mul(self, other: Num) -> Num {
+        self.arith(other, |(a, b), (c, d)| Some((a * c, b * d)), |a, b| a * b)
+    }
+
+    /// Exact division gives a rational when it doesn't divide evenly;
+    /// dividing by exact zero is an error rather than infinity.
+    fn div(self, other: Num, expr: &Expr) -> Result<Num, Box<EvalError>> {
+        if other.parts().is_some_and(|(c, _)| c == 0) && self.parts().is_some() {
+            return Err(Box::new(EvalError::OtherError(format!(
+                "Division by zero in {expr}"
+            ))));
+        }
+        Ok(self.arith(other, |(a, b), (c, d)| Some((a * d, b * c)), |a, b| a / b))
+    }
+
+    fn partial_cmp(self, other: Num) -> Option<Ordering> {
+        match (self.parts(), other.parts()) {
+            // denominators are positive, so cross-multiplying keeps the order
+            (Some((a, b)), Some((c, d))) => Some((a * d).cmp(&(c * b))),
+            _ => self.as_f64().partial_cmp(&other.as_f64()),
+        }
+    }
+}
+
+impl From<Num> for Value {
+    fn from(num: Num) -> Self {
+        match num {
+            Num::Int(n) => Value::Int(n),
+            Num::Rational(num, den) => Value::Rational { num, den },
+            Num::Float(n) => Value::Number(n),
+        }
+    }
+}
+
+fn compare(
+    args: Vec<Value>,
+    expr: Expr,
+    test: fn(Option<Ordering>) -> bool,
+) -> Result<Value, Box<EvalError>> {
+    let first = Num::from_value(&args[0], &expr)?;
+    let second = Num::from_value(&args[1], &expr)?;
+    Ok(Value::Bool(test(first.partial_cmp(second))))
+}
+
+fn hash_key(val: &Value, expr: &Expr) -> Result<ValueKey, Box<EvalError>> {
+    ValueKey::new(val.clone()).ok_or_else(|| {
+        Box::new(EvalError::TypeError {
+            expected: "hashable key".to_string(),
+            found: val.clone(),
+            in_expr: expr.clone(),
+        })
+    })
+}
+
+/// Whether each string argument is ordered by `test` against the next one,
+/// compared case-insensitively with `fold_case`.
+fn compare_strings(
+    args: Vec<Value>,
+    expr: Expr,
+    fold_case: bool,
+    test: fn(Ordering) -> bool,
+) -> Result<Value, Box<EvalError>> {
+    let get = if fold_case {
+        |val: &Value| match val {
+            Value::String(s) => Some(s.to_lowercase()),
+            _ => None,
+        }
+    } else {
+        |val: &Value| match val {
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    };
+    compare_all(args, expr, "String", get, test)
+}
+
+/// Whether each argument is ordered by `test` against the next one, all of
+/// them being the type `get` extracts.
+fn compare_all<T: Ord>(
+    args: Vec<Value>,
+    expr: Expr,
+    expected: &str,
+    get: fn(&Value) -> Option<T>,
+    test: fn(Ordering) -> bool,
+) -> Result<Value, Box<EvalError>> {
+    let items = args
+        .iter()
+        .map(|val| {
+            get(val).ok_or_else(|| {
+                Box::new(EvalError::TypeError {
+                    expected: expected.to_string(),
+                    found: val.clone(),
+                    in_expr: expr.clone(),
+                })
+            })
+        })
+        .collect::<Result<Vec<T>, Box<EvalError>>>()?;
+    Ok(Value::Bool(
+        items.windows(2).all(|pair| test(pair[0].cmp(&pair[1]))),
+    ))
+}
+
+fn as_char(val: &Value) -> Option<char> {
+    match val {
+        Value::Char(c) => Some(*c),
+        _ => None,
+    }
+}
+
+/// Whether the single char argument is in the Unicode class `test` checks.
+fn char_class(
+    args: Vec<Value>,
+    expr: Expr,
+    test: fn(char) -> bool,
+) -> Result<Value, Box<EvalError>> {
+    match &args[0] {
+        Value::Char(c) => Ok(Value::Bool(test(*c))),
+        val => Err(Box::new(EvalError::TypeError {
+            expected: "Char".to_string(),
+            found: val.clone(),
+            in_expr: expr,
+        })),
+    }
+}
+
+/// `min`/`max`: the argument that compares as `keep` against all others. The
+/// result is a float if any argument is, following the arithmetic rules.
+fn extremum(args: Vec<Value>, expr: Expr, keep: Ordering) -> Result<Value, Box<EvalError>> {
+    let nums = args
+        .iter()
+        .map(|val| Num::from_value(val, &expr))
+        .collect::<Result<Vec<Num>, Box<EvalError>>>()?;
+    let mut result = nums[0];
+    for &num in &nums[1..] {
+        if num.partial_cmp(result) == Some(keep) {
+            result = num;
+        }
+    }
+    if nums.iter().any(|num| matches!(num, Num::Float(_))) {
+        result = Num::Float(result.as_f64());
+    }
+    Ok(result.into())
+}
+
+/// The rounding family: integers are returned unchanged, floats are rounded
+/// with `op` and stay floats, fractions are rounded to integers.
+fn round_with(args: Vec<Value>, expr: Expr, op: fn(f64) -> f64) -> Result<Value, Box<EvalError>> {
+    match Num::from_value(&args[0], &expr)? {
+        Num::Int(n) => Ok(Value::Int(n)),
+        Num::Rational(num, den) => Ok(Value::Int(op(num as f64 / den as f64) as i64)),
+        Num::Float(n) => Ok(Value::Number(op(n))),
+    }
+}
+
+/// A non-negative integer used to index into a string or list.
+fn index_arg(val: &Value, expr: &Expr) -> Result<usize, Box<EvalError>> {
+    match val {
+        Value::Int(n) if *n >= 0 => Ok(*n as usize),
+        _ => Err(Box::new(EvalError::TypeError {
+            expected: "Index".to_string(),
+            found: val.clone(),
+            in_expr: expr.clone(),
+        })),
+    }
+}
+
+/// Room for `len` values, failing with an error rather than aborting when
+/// that much memory can't be had.
+fn alloc_values(len: usize, expr: &Expr) -> Result<Vec<Value>, Box<EvalError>> {
+    let mut values = Vec::new();
+    values.try_reserve_exact(len).map_err(|_| {
+        Box::new(EvalError::OtherError(format!(
+            "cannot allocate {len} elements in {expr}"
+        )))
+    })?;
+    Ok(values)
+}
+
+fn radix_arg(val: &Value, expr: &Expr) -> Result<u32, Box<EvalError>> {
+    match val {
+        Value::Int(n) if (2..=36).contains(n) => Ok(*n as u32),
+        _ => Err(Box::new(EvalError::TypeError {
+            expected: "Radix between 2 and 36".to_string(),
+            found: val.clone(),
+            in_expr: expr.clone(),
+        })),
+    }
+}
+
+fn format_radix(n: i64, radix: u32) -> String {
+    if n == 0 {
+        return "0".to_string();
+    }
+    let mut digits = Vec::new();
+    let mut rest = n.unsigned_abs();
+    while rest > 0 {
+        digits.push(std::char::from_digit((rest % radix as u64) as u32, radix).unwrap());
+        rest /= radix as u64;
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    digits.iter().rev().collect()
+}
+
+/// The sublist left after dropping the first `k` pairs of `list`.
+fn list_tail(list: &Value, k: usize, expr: &Expr) -> Result<Value, Box<EvalError>> {
+    let mut cur = list;
+    for _ in 0..k {
+        match cur {
+            Value::Pair(pair) => cur = &pair.1,
+            _ => {
+                return Err(Box::new(EvalError::OtherError(format!(
+                    "Index {k} out of range in {expr}"
+                ))));
+            }
+        }
+    }
+    Ok(cur.clone())
+}
+
+/// `car` and `cdr` applied right to left as spelled by `path`, a run of `a`s
+/// and `d`s like the middle of `cadr`.
+fn cxr(args: Vec<Value>, expr: Expr, path: &str) -> Result<Value, Box<EvalError>> {
+    let mut cur = args[0].clone();
+    for step in path.chars().rev() {
+        let Value::Pair(pair) = &cur else {
+            return Err(Box::new(EvalError::TypeError {
+                expected: "Pair".to_string(),
+                found: cur,
+                in_expr: expr,
+            }));
+        };
+        cur = if step == 'a' {
+            pair.0.clone()
+        } else {
+            pair.1.clone()
+        };
+    }
+    Ok(cur)
+}
+
+/// The single numeric argument of a builtin.
+fn number_arg(args: Vec<Value>, expr: Expr) -> Result<Value, Box<EvalError>> {
+    match &args[0] {
+        val @ (Value::Int(_) | Value::Rational { .. } | Value::Number(_)) => Ok(val.clone()),
+        val => Err(Box::new(EvalError::TypeError {
+            expected: "Number".to_string(),
+            found: val.clone(),
+            in_expr: expr,
+        })),
+    }
+}
+
+/// The elements of each list argument, and how many of them can be walked in
+/// step, the length of the shortest list.
+fn list_args(lists: &[Value], expr: &Expr) -> Result<(Vec<Vec<Value>>, usize), Box<EvalError>> {
+    let lists = lists
+        .iter()
+        .map(|list| {
+            list.list_to_vec().ok_or_else(|| {
+                Box::new(EvalError::TypeError {
+                    expected: "List".to_string(),
+                    found: list.clone(),
+                    in_expr: expr.clone(),
+                })
+            })
+        })
+        .collect::<Result<Vec<Vec<Value>>, Box<EvalError>>>()?;
+    let len = lists.iter().map(Vec::len).min().unwrap_or(0);
+    Ok((lists, len))
+}
+
+/// `reduce` and `reduce-right`, the latter walking the list from its end.
+fn reduce_with(
+    args: Vec<Value>,
+    expr: Expr,
+    env: &mut Env,
+    from_right: bool,
+) -> Result<Value, Box<EvalError>> {
+    if !args[0].is_procedure() {
+        return Err(Box::new(EvalError::TypeError {
+            expected: "function".to_string(),
+            found: args[0].clone(),
+            in_expr: expr,
+        }));
+    }
+    let mut items = args[2].list_to_vec().ok_or_else(|| {
+        Box::new(EvalError::TypeError {
+            expected: "List".to_string(),
+            found: args[2].clone(),
+            in_expr: expr.clone(),
+        })
+    })?;
+    if from_right {
+        items.reverse();
+    }
+    let mut items = items.into_iter();
+    let Some(mut acc) = items.next() else {
+        return Ok(args[1].clone());
+    };
+    for item in items {
+        acc = apply(args[0].clone(), vec![item, acc], &expr, env)?;
+    }
+    Ok(acc)
+}
+
+/// `member` and friends: the first sublist whose car matches `args[0]`
+/// under `eq`, or `#f`.
+fn member_with(
+    args: Vec<Value>,
+    expr: Expr,
+    eq: fn(&Value, &Value) -> bool,
+) -> Result<Value, Box<EvalError>> {
+    let mut cur = &args[1];
+    loop {
+        match cur {
+            Value::Pair(pair) if eq(&pair.0, &args[0]) => return Ok(cur.clone()),
+            Value::Pair(pair) => cur = &pair.1,
+            Value::Nil => return Ok(Value::Bool(false)),
+            _ => {
+                return Err(Box::new(EvalError::TypeError {
+                    expected: "List".to_string(),
+                    found: args[1].clone(),
+                    in_expr: expr,
+                }));
+            }
+        }
+    }
+}
+
+/// `assoc` and friends: the first pair in an association list whose car
+/// matches `args[0]` under `eq`, or `#f`.
+fn assoc_with(
+    args: Vec<Value>,
+    expr: Expr,
+    eq: fn(&Value, &Value) -> bool,
+) -> Result<Value, Box<EvalError>> {
+    let entries = args[1].list_to_vec().ok_or_else(|| {
+        Box::new(EvalError::TypeError {
+            expected: "List".to_string(),
+            found: args[1].clone(),
+            in_expr: expr.clone(),
+        })
+    })?;
+    for entry in entries {
+        match &entry {
+            Value::Pair(pair) if eq(&pair.0, &args[0]) => return Ok(entry),
+            Value::Pair(_) => {}
+            _ => {
+                return Err(Box::new(EvalError::TypeError {
+                    expected: "Pair".to_string(),
+                    found: entry,
+                    in_expr: expr,
+                }));
+            }
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// An integer argument; floats are accepted when they have no fractional part.
+fn integer_arg(val: &Value, expr: &Expr) -> Result<i64, Box<EvalError>> {
+    match Num::from_value(val, expr)? {
+        Num::Int(n) => Ok(n),
+        Num::Float(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(n as i64),
+        Num::Float(_) | Num::Rational(..) => Err(Box::new(EvalError::TypeError {
+            expected: "Integer".to_string(),
+            found: val.clone(),
+            in_expr: expr.clone(),
+        })),
+    }
+}
+
+/// Stable merge sort with a fallible `less` predicate. A comparator that is
+/// not a consistent ordering gives an unspecified order rather than a panic.
+fn merge_sort<T, F>(mut items: Vec<T>, less: &mut F) -> Result<Vec<T>, Box<EvalError>>
+where
+    F: FnMut(&T, &T) -> Result<bool, Box<EvalError>>,
+{
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, less)?;
+    let right = merge_sort(right, less)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // take from the right only when strictly less, for stability
+        if less(b, a)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// Combine integer arguments with a bitwise operator, `init` being its
+/// identity so that zero arguments are allowed.
+fn bitwise_fold(
+    args: Vec<Value>,
+    expr: Expr,
+    init: i64,
+    op: fn(i64, i64) -> i64,
+) -> Result<Value, Box<EvalError>> {
+    let mut result = init;
+    for val in &args {
+        result = op(result, integer_arg(val, &expr)?);
+    }
+    Ok(Value::Int(result))
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::parse_program;
+
+    /// The printed value of the last datum in `source`, or the error message.
+    fn run(source: &str) -> Result<String, String> {
+        colored::control::set_override(false);
+        let mut env = Env::new();
+        let program = parse_program(&mut Tokenizer::new(source).peekable()).unwrap();
+        let mut last = Value::Unspecified;
+        for expr in &program {
+            last = eval(expr, &mut env).map_err(|e| e.to_string())?;
+        }
+        Ok(last.to_string())
+    }
+
+    #[test]
+    fn vectors() {
+        assert_eq!(run("(make-vector 3 'a)").unwrap(), "#(a a a)");
+        assert_eq!(run("(make-vector 2)").unwrap(), "#(0 0)");
+        assert_eq!(run("(vector 1 \"b\" #(2))").unwrap(), "#(1 \"b\" #(2))");
+        assert_eq!(
+            run("(define v (vector 1 2 3)) (vector-set! v 1 'x) v").unwrap(),
+            "#(1 x 3)"
+        );
+        assert_eq!(run("(vector-length #(1 2 3))").unwrap(), "3");
+        assert_eq!(run("(vector-ref #(1 2 3) 2)").unwrap(), "3");
+        assert!(run("(vector-ref #(1 2 3) 3)").is_err());
+        assert!(run("(vector-set! (vector) 0 1)").is_err());
+        assert!(run("(make-vector 100000000000000)").is_err());
+    }
+
+    #[test]
+    fn gcd_and_lcm() {
+        assert_eq!(run("(gcd 12 18)").unwrap(), "6");
+        assert_eq!(run("(lcm 4 6)").unwrap(), "12");
+        assert_eq!(run("(gcd)").unwrap(), "0");
+        assert_eq!(run("(lcm)").unwrap(), "1");
+        assert_eq!(run("(gcd -4 6)").unwrap(), "2");
+        assert!(run("(gcd 1.5 3)").is_err());
+        assert!(run("(gcd -9223372036854775808)").is_err());
+        assert!(run("(lcm -9223372036854775808 3)").is_err());
+    }
+
+    #[test]
+    fn number_output() {
+        assert_eq!(run("(+ 0.1 0.2)").unwrap(), "0.30000000000000004");
+        assert_eq!(run("(/ 10 2)").unwrap(), "5");
+        assert_eq!(
+            run("(= (/ 1.0 3) (string->number (number->string (/ 1.0 3))))").unwrap(),
+            "#t"
+        );
+    }
+
+    #[test]
+    fn iota() {
+        assert_eq!(run("(iota 5)").unwrap(), "(0 1 2 3 4)");
+        assert_eq!(run("(iota 3 1)").unwrap(), "(1 2 3)");
+        assert_eq!(run("(iota 5 1 2)").unwrap(), "(1 3 5 7 9)");
+        assert_eq!(run("(iota 0)").unwrap(), "nil");
+        assert!(run("(iota -1)").is_err());
+        assert!(run("(iota 100000000000000)").is_err());
+    }
+
+    #[test]
+    fn string_split_and_join() {
+        assert_eq!(
+            run("(string-split \"a, b,, c\" \", \")").unwrap(),
+            "(\"a\" \"b,\" \"c\")"
+        );
+        assert_eq!(
+            run("(string-split \"a,,b\" \",\")").unwrap(),
+            "(\"a\" \"\" \"b\")"
+        );
+        assert_eq!(run("(string-split \"\" \",\")").unwrap(), "nil");
+        assert_eq!(run("(string-join '() \",\")").unwrap(), "\"\"");
+        assert_eq!(
+            run("(string-join '(\"a\" \"b\") \"::\")").unwrap(),
+            "\"a::b\""
+        );
+        assert_eq!(
+            run("(string-split \"a,b\" 1)").unwrap_err(),
+            "Type error: expected String, found 1 in (string-split \"a,b\" 1)"
+        );
+        assert!(
+            run("(string-join '(\"a\" 1) \",\")")
+                .unwrap_err()
+                .starts_with("Type error")
+        );
+    }
+
+    #[test]
+    fn take_drop_and_last() {
+        assert_eq!(run("(take '(1 2 3) 2)").unwrap(), "(1 2)");
+        assert_eq!(run("(take '(1 2 3) 0)").unwrap(), "nil");
+        assert_eq!(run("(take '(1 2 3) 3)").unwrap(), "(1 2 3)");
+        assert!(run("(take '(1 2) 3)").is_err());
+        assert!(run("(take '(1 2) 1000000000000000)").is_err());
+        assert_eq!(run("(drop '(1 2 3) 1)").unwrap(), "(2 3)");
+        assert_eq!(run("(drop '(1 2 3) 3)").unwrap(), "nil");
+        assert!(run("(drop '(1 2 3) 4)").is_err());
+        assert_eq!(run("(last '(1 2 3))").unwrap(), "3");
+        assert_eq!(run("(last '(1))").unwrap(), "1");
+        assert!(run("(last '())").is_err());
+    }
+
+    #[test]
+    fn dump_defs_round_trips() {
+        colored::control::set_override(false);
+        let mut env = Env::new();
+        env.load_defs(
+            "(define n 42) (define x (/ 1.0 3)) (define w 5.0) (define l '(1 \"s\" #(2.5)))
+             (define (sq y) (* y y)) (define add (lambda (a . r) (cons a r)))",
+        )
+        .unwrap();
+        let dumped = env.dump_defs();
+        let mut copy = Env::new();
+        copy.load_defs(&dumped).unwrap();
+        assert_eq!(copy.dump_defs(), dumped);
+        let check = "(list n (= x (/ 1.0 3)) (eqv? w 5.0) l (sq 3) (add 1 2))"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            eval(&check, &mut copy).unwrap().to_string(),
+            "(42 #t #t (1 \"s\" #(2.5)) 9 (1 2))"
+        );
+    }
+
+    #[test]
+    fn gensyms_are_fresh_and_unreadable() {
+        assert_eq!(run("(eq? (gensym) (gensym))"), Ok("#f".to_string()));
+        assert_eq!(run("(eq? (gensym) 'g0)"), Ok("#f".to_string()));
+        let sym = run("(gensym)").unwrap();
+        assert!(sym.starts_with("#:g"));
+        let quoted = format!("'{sym}");
+        assert!(parse_program(&mut Tokenizer::new(&quoted).peekable()).is_err());
+    }
+
+    #[test]
+    fn hash_tables() {
+        let setup =
+            "(define t (make-hash-table)) (hash-table-set! t 'a 1) (hash-table-set! t \"b\" 2)";
+        assert_eq!(
+            run(&format!("{setup} (hash-table-ref t 'a)")),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            run(&format!("{setup} (hash-table-ref t \"b\")")),
+            Ok("2".to_string())
+        );
+        assert_eq!(
+            run(&format!(
+                "{setup} (hash-table-set! t 'a 3) (hash-table-ref t 'a)"
+            )),
+            Ok("3".to_string())
+        );
+        assert_eq!(
+            run(&format!(
+                "{setup} (hash-table-delete! t 'a) (hash-table-ref t 'a 'none)"
+            )),
+            Ok("none".to_string())
+        );
+        assert!(run(&format!("{setup} (hash-table-ref t 'c)")).is_err());
+    }
+
+    #[test]
+    fn sort_with_builtin_and_lambda() {
+        assert_eq!(run("(sort '(3 1 2 1) <)"), Ok("(1 1 2 3)".to_string()));
+        assert_eq!(
+            run("(sort '(\"bb\" \"a\" \"ccc\")
+                   (lambda (a b) (> (string-length a) (string-length b))))"),
+            Ok("(\"ccc\" \"bb\" \"a\")".to_string())
+        );
+        assert_eq!(run("(sort '() <)"), Ok("nil".to_string()));
+    }
+
+    #[test]
+    fn builtin_names_are_sorted_and_skip_user_definitions() {
+        let mut env = Env::new();
+        env.define("my-fn", Value::Int(1));
+        let names = env.builtin_names();
+        assert!(names.is_sorted());
+        assert!(names.iter().any(|name| name == "car"));
+        assert!(names.iter().any(|name| name == "+"));
+        assert!(!names.iter().any(|name| name == "my-fn"));
+    }
+
+    #[test]
+    fn clear_empties_the_frame_and_its_closures() {
+        let mut env = Env::new();
+        let program = "(define x 1) (define (f) x)";
+        for expr in parse_program(&mut Tokenizer::new(program).peekable()).unwrap() {
+            eval(&expr, &mut env).unwrap();
+        }
+        let Some(Value::Function(f)) = env.get("f") else {
+            panic!("f is not a function");
+        };
+        env.clear();
+        assert!(!env.is_bound("x"));
+        assert!(!env.is_bound("car"));
+        assert!(!f.env.borrow().is_bound("x"));
+    }
+
+    #[test]
+    fn cxr_accessors() {
+        assert_eq!(run("(cadr '(1 2 3))"), Ok("2".to_string()));
+        assert_eq!(run("(cddr '(1 2 3))"), Ok("(3)".to_string()));
+        assert_eq!(run("(caar '((1) 2))"), Ok("1".to_string()));
+        let err = run("(cadr '(1))").unwrap_err();
+        assert!(err.contains("expected Pair, found nil"), "{err}");
+    }
+
+    #[test]
+    fn defined() {
+        assert_eq!(
+            run("(list (defined? 'car) (defined? 'nope))"),
+            Ok("(#t #f)".to_string())
+        );
+        assert_eq!(
+            run("(define nope 1) (defined? 'nope)"),
+            Ok("#t".to_string())
+        );
+        assert_eq!(run("(let ((y 1)) (defined? 'y))"), Ok("#t".to_string()));
+        assert!(run("(defined? 5)").is_err());
+    }
+
+    #[test]
+    fn exactness() {
+        let source = "(list (exact? 1) (exact? 1/2) (exact? 1.5) (inexact? 1.5) (inexact? 1))";
+        assert_eq!(run(source), Ok("(#t #t #f #t #f)".to_string()));
+        assert_eq!(run("(exact->inexact 1/2)"), Ok("0.5".to_string()));
+        assert_eq!(run("(inexact->exact 2.0)"), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn rational_arithmetic() {
+        assert_eq!(run("(+ 1/3 1/6)"), Ok("1/2".to_string()));
+        assert_eq!(run("(* 2/3 3)"), Ok("2".to_string()));
+        assert_eq!(run("(exact? (* 2/3 3))"), Ok("#t".to_string()));
+        assert_eq!(run("(- 1/2 1/2)"), Ok("0".to_string()));
+        assert_eq!(run("(+ 1/2 0.25)"), Ok("0.75".to_string()));
+    }
+
+    #[test]
+    fn not_is_true_only_for_false() {
+        assert_eq!(
+            run("(list (not #f) (not #t) (not 0) (not '()) (not \"\"))"),
+            Ok("(#t #f #f #f #f)".to_string())
+        );
+    }
+
+    #[test]
+    fn sort_by_calls_the_key_once_per_item() {
+        assert_eq!(
+            run("(sort-by string-length < '(\"ccc\" \"a\" \"bb\"))"),
+            Ok("(\"a\" \"bb\" \"ccc\")".to_string())
+        );
+        let source = "(define n 0)
+            (define sorted (sort-by (lambda (x) (set! n (+ n 1)) (- x)) < '(1 2 3 4)))
+            (list sorted n)";
+        assert_eq!(run(source), Ok("((4 3 2 1) 4)".to_string()));
+    }
+
+    #[test]
+    fn memv_and_assv_compare_with_eqv() {
+        let lists = "(define a (list 1)) (define b (list 1))";
+        let source = format!("{lists} (list (memv a (list b)) (assv a (list (cons b 'x))))");
+        assert_eq!(run(&source), Ok("(#f #f)".to_string()));
+        let source = format!("{lists} (list (member a (list b)) (assoc a (list (cons b 'x))))");
+        assert_eq!(run(&source), Ok("(((1)) ((1) . x))".to_string()));
+        assert_eq!(
+            run("(list (memv 2 '(1 2 3)) (assv 2 '((1 . a) (2 . b))))"),
+            Ok("((2 3) (2 . b))".to_string())
+        );
+    }
+
+    #[test]
+    fn strings_index_by_character() {
+        assert_eq!(run("(string-ref \"héllo✓\" 1)"), Ok("#\\é".to_string()));
+        assert_eq!(run("(string-ref \"héllo✓\" 5)"), Ok("#\\✓".to_string()));
+        assert!(
+            run("(string-ref \"héllo✓\" 6)")
+                .unwrap_err()
+                .contains("Index 6 out of range")
+        );
+        assert_eq!(
+            run("(string-replace-index \"héllo\" 1 #\\e)"),
+            Ok("\"hello\"".to_string())
+        );
+    }
+
+    #[test]
+    fn char_predicates_handle_non_ascii() {
+        let source = "(list (char-alphabetic? #\\λ) (char-upper-case? #\\Ä) (char-lower-case? #\\ä)
+            (char-numeric? #\\٣) (char-alphabetic? #\\1) (char-whitespace? (string-ref \"a\u{3000}\" 1)))";
+        assert_eq!(run(source), Ok("(#t #t #t #t #f #t)".to_string()));
     }
 }
@@ -1,275 +1,1357 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    fmt::Display,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use crate::{
     ast::Expr,
     env::Env,
-    value::{BuiltinFunc, UserFunction, Value},
+    lexer::{ParseError, Span, SpanTree},
+    macros::Macro,
+    symbol::{self, Symbol},
+    value::{Promise, RecordProc, RecordProcKind, RecordType, UserFunction, Value},
 };
 
+/// Names `eval` handles itself rather than by calling what they are bound to,
+/// unless the program binds them.
+pub(crate) const SPECIAL_FORMS: &[Symbol] = &[
+    symbol::DEFINE,
+    symbol::SET,
+    symbol::DEFINE_SYNTAX,
+    symbol::DEFINE_RECORD_TYPE,
+    symbol::DEFINE_VALUES,
+    symbol::BEGIN,
+    symbol::QUOTE,
+    symbol::QUASIQUOTE,
+    symbol::UNQUOTE,
+    symbol::UNQUOTE_SPLICING,
+    symbol::DELAY,
+    symbol::PARAMETERIZE,
+    symbol::LAMBDA,
+    symbol::CASE_LAMBDA,
+    symbol::LET,
+    symbol::AND,
+    symbol::OR,
+    symbol::NOT,
+    symbol::IF,
+    symbol::WHEN,
+    symbol::UNLESS,
+    symbol::COND,
+    symbol::GUARD,
+    symbol::DO,
+    symbol::CASE,
+];
+
+/// Frames shown by an error's backtrace; the rest are only counted.
+const BACKTRACE_LIMIT: usize = 10;
+
 pub fn eval(expr: &Expr, env: &mut Env) -> Result<Value, Box<EvalError>> {
-    match expr {
-        Expr::Number(n) => Ok(Value::Number(*n)),
-        Expr::Symbol(s) => Ok(env.get(s).ok_or(EvalError::UnboundSymbol(s.clone()))?),
-        Expr::List(list) => {
-            if list.is_empty() {
-                return Ok(Value::Nil);
-            }
-            match &list[0] {
-                Expr::Symbol(s) if s == "define" => {
-                    if list.len() != 3 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "define requires 2 arguments.".to_string(),
-                        }));
-                    }
-                    match &list[1] {
-                        Expr::Symbol(name) => {
-                            let val = eval(&list[2], env)?;
-                            env.define(name, val.clone());
-                            Ok(val)
-                        }
-                        Expr::List(fn_decl) => {
-                            if let Some(Expr::Symbol(name)) = fn_decl.first() {
-                                let params: Vec<String> = fn_decl[1..]
-                                    .iter()
-                                    .map(|p| match p {
-                                        Expr::Symbol(s) => Ok(s.clone()),
-                                        _ => Err(EvalError::InvalidSyntax {
-                                            expr: expr.clone(),
-                                            desc: "Function parameters must be symbols."
-                                                .to_string(),
-                                        }),
-                                    })
-                                    .collect::<Result<Vec<_>, EvalError>>()?;
-                                let body = list[2].clone();
-                                // manually evaluate the function body
-                                let func_env = Rc::new(RefCell::new(env.clone()));
-                                let val = Value::Function(UserFunction {
-                                    params: params.clone(),
-                                    body: body.clone(),
-                                    env: Rc::clone(&func_env),
-                                    name: Some(name.clone()),
-                                });
-                                func_env.borrow_mut().vars.insert(name.clone(), val.clone());
-
-                                env.define(name, val.clone());
-                                Ok(val)
-                            } else {
+    let _depth = DepthGuard::enter(env)?;
+    let mut tail_calls = TailCalls::default();
+    eval_frame(expr, env, &mut tail_calls).map_err(|e| {
+        // innermost first, like the frames of the calls this one is under
+        tail_calls
+            .0
+            .into_iter()
+            .rev()
+            .fold(e, |e, name| e.in_function(name))
+    })
+}
+
+/// The functions an `eval` went through by tail calls, oldest first, for
+/// the backtrace of an error. A function tail-calling itself is recorded
+/// once, and only the latest `BACKTRACE_LIMIT` are kept, as many as a
+/// backtrace shows.
+#[derive(Default)]
+struct TailCalls(Vec<String>);
+
+impl TailCalls {
+    fn enter(&mut self, name: &str) {
+        if self.0.last().is_some_and(|last| last == name) {
+            return;
+        }
+        if self.0.len() == BACKTRACE_LIMIT {
+            self.0.remove(0);
+        }
+        self.0.push(name.to_string());
+    }
+}
+
+fn eval_frame(
+    expr: &Expr,
+    env: &mut Env,
+    tail_calls: &mut TailCalls,
+) -> Result<Value, Box<EvalError>> {
+    // Expressions in tail position (if branches, let and function bodies)
+    // are evaluated by looping with the next expression and frame instead of
+    // recursing, so tail-recursive loops run in constant stack space.
+    let mut current = Cow::Borrowed(expr);
+    let mut frame: Option<Env> = None;
+    'tail: loop {
+        let expr = current.as_ref();
+        let env = match frame.as_mut() {
+            Some(frame) => frame,
+            None => &mut *env,
+        };
+        env.step()?;
+        return match expr {
+            Expr::Bool(b) => Ok(Value::Bool(*b)),
+            Expr::Int(n) => Ok(Value::Int(*n)),
+            Expr::Number(n) => Ok(Value::Number(*n)),
+            Expr::Rational { num, den } => Ok(Value::Rational {
+                num: *num,
+                den: *den,
+            }),
+            Expr::String(s) => Ok(Value::String(s.clone())),
+            Expr::Char(c) => Ok(Value::Char(*c)),
+            // vector literals are self-evaluating
+            Expr::Vector(_) => Ok(quote(expr)),
+            Expr::Symbol(s) => Ok(env
+                .get(s)
+                .ok_or_else(|| EvalError::UnboundSymbol(s.to_string()))?),
+            Expr::List(list) => {
+                if list.is_empty() {
+                    return Ok(Value::Nil);
+                }
+                // a binding of a special form's name, like `(define (if ...) ...)`,
+                // takes precedence over the special form. Until some frame
+                // binds one, which is rare, there is no lookup to make.
+                let shadowed = env.special_forms_bound.get()
+                    && matches!(&list[0], Expr::Symbol(s) if SPECIAL_FORMS.contains(s) && env.is_bound(s));
+                match &list[0] {
+                    Expr::Symbol(s) if *s == symbol::DEFINE && !shadowed => {
+                        if list.len() < 3 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "define requires 2 arguments.".to_string(),
+                            }));
+                        }
+                        match &list[1] {
+                            Expr::Symbol(_) if list.len() != 3 => {
                                 Err(Box::new(EvalError::InvalidSyntax {
                                     expr: expr.clone(),
-                                    desc: "Function name must be a symbol.".to_string(),
+                                    desc: "define requires 2 arguments.".to_string(),
                                 }))
                             }
+                            Expr::Symbol(name) => {
+                                let val = eval(&list[2], env)?;
+                                env.define(name, val);
+                                Ok(Value::Unspecified)
+                            }
+                            Expr::List(fn_decl) => {
+                                if let Some(Expr::Symbol(name)) = fn_decl.first() {
+                                    let (params, rest) = parse_params(&fn_decl[1..], expr)?;
+                                    let body = sequence(&list[2..]);
+                                    // the function sees itself through the frame it is defined in
+                                    let val = Value::Function(UserFunction {
+                                        params,
+                                        rest,
+                                        body,
+                                        env: Rc::new(RefCell::new(env.clone())),
+                                        name: Some(name.to_string()),
+                                    });
+                                    env.define(name, val);
+                                    Ok(Value::Unspecified)
+                                } else {
+                                    Err(Box::new(EvalError::InvalidSyntax {
+                                        expr: expr.clone(),
+                                        desc: "Function name must be a symbol.".to_string(),
+                                    }))
+                                }
+                            }
+                            _ => Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "define requires a symbol or a list".to_string(),
+                            })),
                         }
-                        _ => Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "define requires a symbol or a list".to_string(),
-                        })),
                     }
-                }
-                Expr::Symbol(s) if s == "lambda" => {
-                    if list.len() != 3 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "lambda requires 2 arguments".to_string(),
-                        }));
+                    Expr::Symbol(s) if *s == symbol::SET && !shadowed => {
+                        let [_, Expr::Symbol(name), value] = list.as_slice() else {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "set! requires a symbol and a value".to_string(),
+                            }));
+                        };
+                        let val = eval(value, env)?;
+                        env.set(name, val)?;
+                        Ok(Value::Unspecified)
                     }
-                    let params = match &list[1] {
-                        Expr::List(l) => l
-                            .iter()
-                            .map(|e| match e {
-                                Expr::Symbol(s) => Ok(s.clone()),
-                                _ => Err(EvalError::InvalidSyntax {
+                    Expr::Symbol(s) if *s == symbol::DEFINE_SYNTAX && !shadowed => {
+                        let (name, rules) = match &list[1..] {
+                            [Expr::Symbol(name), Expr::List(rules)] => (name, rules),
+                            _ => {
+                                return Err(Box::new(EvalError::InvalidSyntax {
                                     expr: expr.clone(),
-                                    desc: "lambda parameters must be symbols".to_string(),
-                                }),
+                                    desc: "define-syntax requires a name and a syntax-rules form"
+                                        .to_string(),
+                                }));
+                            }
+                        };
+                        let mac = parse_syntax_rules(name, rules).ok_or_else(|| {
+                            Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc:
+                                    "expected (syntax-rules (literal ...) (pattern template) ...)"
+                                        .to_string(),
                             })
-                            .collect::<Result<Vec<_>, EvalError>>()?,
-                        _ => {
+                        })?;
+                        env.define(name, Value::Macro(mac));
+                        Ok(Value::Unspecified)
+                    }
+                    Expr::Symbol(s) if *s == symbol::DEFINE_RECORD_TYPE && !shadowed => {
+                        define_record_type(&list[1..], expr, env)
+                    }
+                    // `(define-values (name ... [. rest]) expr)` binds each of the
+                    // values `expr` returns to a name, like the parameters of a call
+                    Expr::Symbol(s) if *s == symbol::DEFINE_VALUES && !shadowed => {
+                        let [_, Expr::List(formals), value] = list.as_slice() else {
                             return Err(Box::new(EvalError::InvalidSyntax {
                                 expr: expr.clone(),
-                                desc: "lambda parameters must be a list".to_string(),
+                                desc: "define-values requires a list of names and an expression"
+                                    .to_string(),
+                            }));
+                        };
+                        let (names, rest) = parse_params(formals, expr)?;
+                        let values = match eval(value, env)? {
+                            Value::Values(values) => values,
+                            value => vec![value],
+                        };
+                        let arity_ok = match rest {
+                            Some(_) => values.len() >= names.len(),
+                            None => values.len() == names.len(),
+                        };
+                        if !arity_ok {
+                            return Err(Box::new(EvalError::ArityMismatch {
+                                name: None,
+                                min: names.len(),
+                                max: rest.is_none().then_some(names.len()),
+                                found: values.len(),
+                                in_expr: expr.clone(),
                             }));
                         }
-                    };
-                    let body = list[2].clone();
-                    Ok(Value::Function(UserFunction {
-                        params,
-                        body,
-                        env: Rc::new(RefCell::new(env.clone())),
-                        name: None,
-                    }))
-                }
-                Expr::Symbol(s) if s == "and" => {
-                    if list.len() < 3 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
+                        let mut values = values.into_iter();
+                        for (name, val) in names.iter().zip(values.by_ref()) {
+                            env.define(name, val);
+                        }
+                        if let Some(rest) = rest {
+                            env.define(rest, Value::list(values.collect()));
+                        }
+                        Ok(Value::Unspecified)
+                    }
+                    Expr::Symbol(s) if *s == symbol::BEGIN && !shadowed => {
+                        let Some((last, init)) = list[1..].split_last() else {
+                            return Ok(Value::Unspecified);
+                        };
+                        for form in init {
+                            eval(form, env)?;
+                        }
+                        current = Cow::Owned(last.clone());
+                        continue 'tail;
+                    }
+                    Expr::Symbol(s) if *s == symbol::QUOTE && !shadowed => {
+                        if list.len() != 2 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "quote requires 1 argument".to_string(),
+                            }));
+                        }
+                        Ok(quote(&list[1]))
+                    }
+                    Expr::Symbol(s) if *s == symbol::QUASIQUOTE && !shadowed => {
+                        if list.len() != 2 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "quasiquote requires 1 argument".to_string(),
+                            }));
+                        }
+                        quasiquote(&list[1], 1, env)
+                    }
+                    Expr::Symbol(s)
+                        if (*s == symbol::UNQUOTE || *s == symbol::UNQUOTE_SPLICING)
+                            && !shadowed =>
+                    {
+                        Err(Box::new(EvalError::InvalidSyntax {
                             expr: expr.clone(),
-                            desc: "and requires at least 2 arguments".to_string(),
-                        }));
+                            desc: format!("{s} is only valid inside quasiquote"),
+                        }))
                     }
-                    for arg in &list[1..] {
-                        let val = eval(arg, env)?;
-                        match val {
-                            Value::Bool(true) => {
-                                continue;
-                            }
-                            Value::Bool(false) => {
-                                return Ok(Value::Bool(false));
-                            }
-                            _ => {
+                    Expr::Symbol(s) if *s == symbol::DELAY && !shadowed => {
+                        if list.len() != 2 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "delay requires 1 argument".to_string(),
+                            }));
+                        }
+                        Ok(Value::Promise(Rc::new(RefCell::new(Promise::Delayed {
+                            expr: list[1].clone(),
+                            env: Rc::new(RefCell::new(env.clone())),
+                        }))))
+                    }
+                    // `(parameterize ((param value) ...) body)` gives each parameter
+                    // its value while the body runs, restoring the old values however
+                    // it is left, so the body isn't in tail position
+                    Expr::Symbol(s) if *s == symbol::PARAMETERIZE && !shadowed => {
+                        let [_, Expr::List(bindings), body @ ..] = list.as_slice() else {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "parameterize requires a list of bindings".to_string(),
+                            }));
+                        };
+                        if body.is_empty() {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "parameterize requires a body".to_string(),
+                            }));
+                        }
+                        let mut params = Vec::new();
+                        for binding in bindings {
+                            let Expr::List(binding) = binding else {
                                 return Err(Box::new(EvalError::InvalidSyntax {
                                     expr: expr.clone(),
-                                    desc: "and requires boolean arguments".to_string(),
+                                    desc: "parameterize bindings must be (parameter value) pairs"
+                                        .to_string(),
                                 }));
-                            }
+                            };
+                            let [param, value] = binding.as_slice() else {
+                                return Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: "parameterize bindings must be (parameter value) pairs"
+                                        .to_string(),
+                                }));
+                            };
+                            let param = match eval(param, env)? {
+                                Value::Parameter(param) => param,
+                                found => {
+                                    return Err(Box::new(EvalError::TypeError {
+                                        expected: "Parameter".to_string(),
+                                        found,
+                                        in_expr: expr.clone(),
+                                    }));
+                                }
+                            };
+                            params.push((param, eval(value, env)?));
+                        }
+                        for (param, value) in &mut params {
+                            std::mem::swap(&mut *param.borrow_mut(), value);
                         }
+                        // like a `let` body, definitions stay local to it
+                        let result = eval(&sequence(body), &mut env.new_child());
+                        // swapping back in reverse undoes a parameter bound twice
+                        for (param, value) in params.iter_mut().rev() {
+                            std::mem::swap(&mut *param.borrow_mut(), value);
+                        }
+                        result
                     }
-                    Ok(Value::Bool(true))
-                }
-                Expr::Symbol(s) if s == "or" => {
-                    if list.len() < 3 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "or requires at least 2 arguments".to_string(),
-                        }));
+                    Expr::Symbol(s) if *s == symbol::LAMBDA && !shadowed => {
+                        if list.len() < 3 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "lambda requires parameters and a body".to_string(),
+                            }));
+                        }
+                        let (params, rest) = lambda_params(&list[1], expr)?;
+                        let body = sequence(&list[2..]);
+                        Ok(Value::Function(UserFunction {
+                            params,
+                            rest,
+                            body,
+                            env: Rc::new(RefCell::new(env.clone())),
+                            name: None,
+                        }))
                     }
-                    for arg in &list[1..] {
-                        let val = eval(arg, env)?;
-                        match val {
-                            Value::Bool(true) => {
-                                return Ok(Value::Bool(true));
-                            }
-                            Value::Bool(false) => {
-                                continue;
+                    // `(case-lambda (params body...) ...)` calls the first clause
+                    // taking as many arguments as it was given
+                    Expr::Symbol(s) if *s == symbol::CASE_LAMBDA && !shadowed => {
+                        let func_env = Rc::new(RefCell::new(env.clone()));
+                        let clauses = list[1..]
+                            .iter()
+                            .map(|clause| match clause {
+                                Expr::List(clause) if clause.len() >= 2 => {
+                                    let (params, rest) = lambda_params(&clause[0], expr)?;
+                                    Ok(UserFunction {
+                                        params,
+                                        rest,
+                                        body: sequence(&clause[1..]),
+                                        env: Rc::clone(&func_env),
+                                        name: None,
+                                    })
+                                }
+                                _ => Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: "case-lambda clauses must be (parameters body...)"
+                                        .to_string(),
+                                })),
+                            })
+                            .collect::<Result<Vec<UserFunction>, Box<EvalError>>>()?;
+                        Ok(Value::CaseLambda(Rc::new(clauses)))
+                    }
+                    Expr::Symbol(s) if *s == symbol::LET && !shadowed => {
+                        // `(let name ((var init) ...) body)` is a named let, binding
+                        // `name` to a procedure over the vars within the body
+                        let (name, bindings, body) = match &list[1..] {
+                            [Expr::Symbol(name), bindings, body @ ..] if !body.is_empty() => {
+                                (Some(name), bindings, body)
                             }
+                            [bindings, body @ ..] if !body.is_empty() => (None, bindings, body),
                             _ => {
                                 return Err(Box::new(EvalError::InvalidSyntax {
                                     expr: expr.clone(),
-                                    desc: "or requires boolean arguments".to_string(),
+                                    desc: "let requires a list of bindings and a body".to_string(),
                                 }));
                             }
+                        };
+                        let Expr::List(bindings) = bindings else {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "let bindings must be a list".to_string(),
+                            }));
+                        };
+                        let mut params = Vec::new();
+                        let mut args = Vec::new();
+                        for binding in bindings {
+                            let binding = match binding {
+                                Expr::List(binding) => binding.as_slice(),
+                                _ => &[],
+                            };
+                            let [Expr::Symbol(var), init] = binding else {
+                                return Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: "let bindings must be (symbol value) pairs".to_string(),
+                                }));
+                            };
+                            params.push(*var);
+                            args.push(eval(init, env)?);
+                        }
+                        // the name is bound in a frame of its own, between the
+                        // enclosing one and the body
+                        let func_env = Rc::new(RefCell::new(env.new_child()));
+                        let func = UserFunction {
+                            params,
+                            rest: None,
+                            body: sequence(body),
+                            env: Rc::clone(&func_env),
+                            name: name.map(|name| name.to_string()),
+                        };
+                        if let Some(name) = name {
+                            func_env
+                                .borrow_mut()
+                                .define(name, Value::Function(func.clone()));
+                            tail_calls.enter(name);
                         }
+                        let local_env = bind_params(&func, args, expr)?;
+                        current = Cow::Owned(func.body);
+                        frame = Some(local_env);
+                        continue 'tail;
                     }
-                    Ok(Value::Bool(false))
-                }
-                Expr::Symbol(s) if s == "not" => {
-                    if list.len() != 2 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "not requires 1 argument".to_string(),
-                        }));
+                    // the last operand of `and`/`or` is in tail position, so its
+                    // value is returned as is rather than checked to be a bool
+                    Expr::Symbol(s) if *s == symbol::AND && !shadowed => {
+                        let Some((last, init)) = list[1..].split_last() else {
+                            return Ok(Value::Bool(true));
+                        };
+                        for arg in init {
+                            let val = eval(arg, env)?;
+                            match val {
+                                Value::Bool(true) => {
+                                    continue;
+                                }
+                                Value::Bool(false) => {
+                                    return Ok(Value::Bool(false));
+                                }
+                                _ => {
+                                    return Err(Box::new(EvalError::InvalidSyntax {
+                                        expr: expr.clone(),
+                                        desc: "and requires boolean arguments".to_string(),
+                                    }));
+                                }
+                            }
+                        }
+                        current = Cow::Owned(last.clone());
+                        continue 'tail;
                     }
-                    let val = eval(&list[1], env)?;
-                    match val {
-                        Value::Bool(b) => Ok(Value::Bool(!b)),
-                        _ => Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "not requires a boolean argument".to_string(),
-                        })),
+                    Expr::Symbol(s) if *s == symbol::OR && !shadowed => {
+                        let Some((last, init)) = list[1..].split_last() else {
+                            return Ok(Value::Bool(false));
+                        };
+                        for arg in init {
+                            let val = eval(arg, env)?;
+                            match val {
+                                Value::Bool(true) => {
+                                    return Ok(Value::Bool(true));
+                                }
+                                Value::Bool(false) => {
+                                    continue;
+                                }
+                                _ => {
+                                    return Err(Box::new(EvalError::InvalidSyntax {
+                                        expr: expr.clone(),
+                                        desc: "or requires boolean arguments".to_string(),
+                                    }));
+                                }
+                            }
+                        }
+                        current = Cow::Owned(last.clone());
+                        continue 'tail;
                     }
-                }
-                Expr::Symbol(s) if s == "if" => {
-                    if list.len() != 4 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "if requires 3 arguments".to_string(),
-                        }));
+                    Expr::Symbol(s) if *s == symbol::NOT && !shadowed => {
+                        if list.len() != 2 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "not requires 1 argument".to_string(),
+                            }));
+                        }
+                        // only #f is false, any other value is true
+                        let val = eval(&list[1], env)?;
+                        Ok(Value::Bool(matches!(val, Value::Bool(false))))
                     }
-                    let cond = eval(&list[1], env)?;
-                    match cond {
-                        Value::Bool(true) => eval(&list[2], env),
-                        Value::Bool(false) => eval(&list[3], env),
-                        _ => Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "if condition must be a boolean".to_string(),
-                        })),
+                    Expr::Symbol(s) if *s == symbol::IF && !shadowed => {
+                        if list.len() != 4 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "if requires 3 arguments".to_string(),
+                            }));
+                        }
+                        let cond = eval(&list[1], env)?;
+                        let branch = match cond {
+                            Value::Bool(true) => &list[2],
+                            Value::Bool(false) => &list[3],
+                            _ => {
+                                return Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: "if condition must be a boolean".to_string(),
+                                }));
+                            }
+                        };
+                        current = Cow::Owned(branch.clone());
+                        continue 'tail;
                     }
-                }
-                Expr::Symbol(s) if s == "cond" => {
-                    if list.len() < 3 {
-                        return Err(Box::new(EvalError::InvalidSyntax {
-                            expr: expr.clone(),
-                            desc: "cond requires at least 2 arguments".to_string(),
-                        }));
+                    // `(when test body ...)` runs the body if the test holds,
+                    // `unless` if it doesn't, its last expression in tail position
+                    Expr::Symbol(s)
+                        if (*s == symbol::WHEN || *s == symbol::UNLESS) && !shadowed =>
+                    {
+                        if list.len() < 3 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: format!("{s} requires a test and a body"),
+                            }));
+                        }
+                        let run = match eval(&list[1], env)? {
+                            Value::Bool(b) => b == (*s == symbol::WHEN),
+                            _ => {
+                                return Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: format!("{s} condition must be a boolean"),
+                                }));
+                            }
+                        };
+                        if !run {
+                            return Ok(Value::Unspecified);
+                        }
+                        current = Cow::Owned(sequence(&list[2..]));
+                        continue 'tail;
                     }
-                    for pair in &list[1..] {
-                        if let Expr::List(l) = pair {
-                            if l.len() != 2 {
+                    Expr::Symbol(s) if *s == symbol::COND && !shadowed => {
+                        if list.len() < 3 {
+                            return Err(Box::new(EvalError::InvalidSyntax {
+                                expr: expr.clone(),
+                                desc: "cond requires at least 2 arguments".to_string(),
+                            }));
+                        }
+                        for pair in &list[1..] {
+                            if let Expr::List(l) = pair {
+                                if l.len() < 2 {
+                                    return Err(Box::new(EvalError::InvalidSyntax {
+                                        expr: expr.clone(),
+                                        desc: "cond requires a list of pairs".to_string(),
+                                    }));
+                                }
+                                // a clause body is an implicit `begin`
+                                let (cond, body) = (&l[0], &l[1..]);
+                                if let Expr::Symbol(s) = cond
+                                    && *s == symbol::ELSE
+                                {
+                                    current = Cow::Owned(sequence(body));
+                                    continue 'tail;
+                                }
+                                let cond_val = eval(cond, env)?;
+                                if let Value::Bool(flag) = cond_val {
+                                    if flag {
+                                        current = Cow::Owned(sequence(body));
+                                        continue 'tail;
+                                    }
+                                } else {
+                                    return Err(Box::new(EvalError::TypeError {
+                                        expected: "bool".to_string(),
+                                        found: cond_val,
+                                        in_expr: cond.clone(),
+                                    }));
+                                }
+                            } else {
                                 return Err(Box::new(EvalError::InvalidSyntax {
                                     expr: expr.clone(),
                                     desc: "cond requires a list of pairs".to_string(),
                                 }));
                             }
-                            let (cond, val) = (&l[0], &l[1]);
-                            if let Expr::Symbol(s) = cond {
-                                if s == "else" {
-                                    return eval(val, env);
+                        }
+                        Err(Box::new(EvalError::InvalidSyntax {
+                            expr: expr.clone(),
+                            desc: "cond requires at least one else clause".to_string(),
+                        }))
+                    }
+                    Expr::Symbol(s) if *s == symbol::GUARD && !shadowed => {
+                        // `(guard (var clause ...) body ...)` evaluates the body,
+                        // and if it raises binds the condition to `var` and picks
+                        // a `cond`-style clause. Without a match it raises again.
+                        let (var, clauses, body) = match &list[1..] {
+                            [Expr::List(spec), body @ ..] if !body.is_empty() => {
+                                match spec.split_first() {
+                                    Some((Expr::Symbol(var), clauses)) => (var, clauses, body),
+                                    _ => {
+                                        return Err(Box::new(EvalError::InvalidSyntax {
+                                            expr: expr.clone(),
+                                            desc: "guard requires (var clause ...)".to_string(),
+                                        }));
+                                    }
                                 }
                             }
-                            let cond_val = eval(cond, env)?;
-                            if let Value::Bool(flag) = cond_val {
-                                if flag {
-                                    return eval(val, env);
+                            _ => {
+                                return Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: "guard requires (var clause ...) and a body".to_string(),
+                                }));
+                            }
+                        };
+                        let condition = match eval(&sequence(body), &mut env.new_child()) {
+                            Err(e) if e.is_catchable() => (*e).into_condition(),
+                            result => return result,
+                        };
+                        let mut guard_env = env.new_child();
+                        guard_env.define(var, condition.clone());
+                        for clause in clauses {
+                            let Expr::List(clause) = clause else {
+                                return Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: "guard clauses must be lists".to_string(),
+                                }));
+                            };
+                            let Some((test, body)) = clause.split_first() else {
+                                return Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: "guard clauses must have a test".to_string(),
+                                }));
+                            };
+                            if !matches!(test, Expr::Symbol(s) if *s == symbol::ELSE) {
+                                match eval(test, &mut guard_env)? {
+                                    Value::Bool(false) => continue,
+                                    Value::Bool(true) => {}
+                                    found => {
+                                        return Err(Box::new(EvalError::TypeError {
+                                            expected: "bool".to_string(),
+                                            found,
+                                            in_expr: test.clone(),
+                                        }));
+                                    }
                                 }
-                            } else {
-                                return Err(Box::new(EvalError::TypeError {
-                                    expected: "bool".to_string(),
-                                    found: cond_val,
-                                    in_expr: cond.clone(),
+                            }
+                            if body.is_empty() {
+                                return Ok(Value::Bool(true));
+                            }
+                            current = Cow::Owned(sequence(body));
+                            frame = Some(guard_env);
+                            continue 'tail;
+                        }
+                        Err(Box::new(EvalError::from_condition(condition)))
+                    }
+                    Expr::Symbol(s) if *s == symbol::DO && !shadowed => {
+                        // `(do ((var init step) ...) (test result ...) command ...)`
+                        let (specs, test, results, commands) = match &list[1..] {
+                            [Expr::List(specs), Expr::List(exit), commands @ ..]
+                                if !exit.is_empty() =>
+                            {
+                                (specs, &exit[0], &exit[1..], commands)
+                            }
+                            _ => {
+                                return Err(Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc:
+                                        "do requires variable specs and a (test result ...) clause"
+                                            .to_string(),
                                 }));
                             }
-                        } else {
+                        };
+                        let mut vars = Vec::new();
+                        let mut inits = Vec::new();
+                        for spec in specs {
+                            let spec = match spec {
+                                Expr::List(spec) => spec.as_slice(),
+                                _ => &[],
+                            };
+                            let (var, init, step) = match spec {
+                                [Expr::Symbol(var), init] => (var, init, None),
+                                [Expr::Symbol(var), init, step] => (var, init, Some(step)),
+                                _ => {
+                                    return Err(Box::new(EvalError::InvalidSyntax {
+                                        expr: expr.clone(),
+                                        desc: "do variables must be (var init [step])".to_string(),
+                                    }));
+                                }
+                            };
+                            vars.push((var, step));
+                            inits.push(eval(init, env)?);
+                        }
+                        // the variables live in a frame of their own, updated in
+                        // place each iteration
+                        let mut loop_env = env.new_child();
+                        for ((var, _), val) in vars.iter().zip(inits) {
+                            loop_env.define(*var, val);
+                        }
+                        loop {
+                            match eval(test, &mut loop_env)? {
+                                Value::Bool(true) => break,
+                                Value::Bool(false) => {}
+                                found => {
+                                    return Err(Box::new(EvalError::TypeError {
+                                        expected: "bool".to_string(),
+                                        found,
+                                        in_expr: test.clone(),
+                                    }));
+                                }
+                            }
+                            for command in commands {
+                                eval(command, &mut loop_env)?;
+                            }
+                            // every step sees the variables of the previous iteration
+                            let mut steps = Vec::new();
+                            for (var, step) in &vars {
+                                if let Some(step) = step {
+                                    steps.push((*var, eval(step, &mut loop_env)?));
+                                }
+                            }
+                            for (var, val) in steps {
+                                loop_env.define(*var, val);
+                            }
+                        }
+                        if results.is_empty() {
+                            return Ok(Value::Unspecified);
+                        }
+                        current = Cow::Owned(sequence(results));
+                        frame = Some(loop_env);
+                        continue 'tail;
+                    }
+                    Expr::Symbol(s) if *s == symbol::CASE && !shadowed => {
+                        // `(case key ((datum ...) body ...) ... (else body ...))`
+                        // compares the key to each datum with `eqv?`
+                        if list.len() < 2 {
                             return Err(Box::new(EvalError::InvalidSyntax {
                                 expr: expr.clone(),
-                                desc: "cond requires a list of pairs".to_string(),
+                                desc: "case requires a key".to_string(),
                             }));
                         }
+                        let key = eval(&list[1], env)?;
+                        for clause in &list[2..] {
+                            let (data, body) = match clause {
+                                Expr::List(l) if l.len() >= 2 => (&l[0], &l[1..]),
+                                _ => {
+                                    return Err(Box::new(EvalError::InvalidSyntax {
+                                        expr: expr.clone(),
+                                        desc: "case clauses must be ((datum ...) body ...)"
+                                            .to_string(),
+                                    }));
+                                }
+                            };
+                            let matched = match data {
+                                Expr::Symbol(s) if *s == symbol::ELSE => true,
+                                Expr::List(data) => data.iter().any(|d| quote(d).is_eqv(&key)),
+                                _ => {
+                                    return Err(Box::new(EvalError::InvalidSyntax {
+                                        expr: expr.clone(),
+                                        desc: "case clauses must start with a list of data"
+                                            .to_string(),
+                                    }));
+                                }
+                            };
+                            if matched {
+                                current = Cow::Owned(sequence(body));
+                                continue 'tail;
+                            }
+                        }
+                        Ok(Value::Unspecified)
                     }
-                    Err(Box::new(EvalError::InvalidSyntax {
-                        expr: expr.clone(),
-                        desc: "cond requires at least one else clause".to_string(),
-                    }))
-                }
-                func_expr => {
-                    let func = eval(func_expr, env)?;
-                    let args: Vec<Value> = list[1..]
-                        .iter()
-                        .map(|arg| eval(arg, env))
-                        .collect::<Result<Vec<Value>, Box<EvalError>>>()?;
-                    match func {
-                        Value::BuiltinFunction(BuiltinFunc { func: f, .. }) => {
-                            f(args, expr.clone())
-                        }
-                        Value::Function(UserFunction {
-                            params,
-                            body,
-                            env: func_env,
-                            name: _,
-                        }) => {
-                            if params.len() != args.len() {
-                                return Err(Box::new(EvalError::ArityMismatch {
-                                    expected: params.len(),
-                                    found: args.len(),
-                                    in_expr: expr.clone(),
-                                }));
+                    func_expr => {
+                        let func = eval(func_expr, env)?;
+                        if let Value::Macro(mac) = &func {
+                            let expansion = mac.expand(expr).ok_or_else(|| {
+                                Box::new(EvalError::InvalidSyntax {
+                                    expr: expr.clone(),
+                                    desc: format!("no syntax rule of {} matches", mac.name),
+                                })
+                            })?;
+                            current = Cow::Owned(expansion);
+                            continue 'tail;
+                        }
+                        let args: Vec<Value> = list[1..]
+                            .iter()
+                            .map(|arg| eval(arg, env))
+                            .collect::<Result<Vec<Value>, Box<EvalError>>>()?;
+                        match func {
+                            Value::Function(func) => {
+                                let local_env = bind_params(&func, args, expr)?;
+                                tail_calls.enter(frame_name(&func));
+                                current = Cow::Owned(func.body);
+                                frame = Some(local_env);
+                                continue 'tail;
                             }
-                            let mut local_env = func_env.borrow().clone();
-                            for (name, val) in params.iter().zip(args.into_iter()) {
-                                local_env.define(name, val);
+                            Value::CaseLambda(clauses) => {
+                                let func = select_clause(&clauses, args.len(), expr)?;
+                                let local_env = bind_params(func, args, expr)?;
+                                tail_calls.enter(frame_name(func));
+                                current = Cow::Owned(func.body.clone());
+                                frame = Some(local_env);
+                                continue 'tail;
                             }
-
-                            eval(&body, &mut local_env)
+                            func => apply(func, args, expr, env),
                         }
-                        _ => Err(Box::new(EvalError::TypeError {
-                            expected: "function".to_string(),
-                            found: func,
-                            in_expr: func_expr.clone(),
-                        })),
                     }
                 }
             }
+        };
+    }
+}
+
+/// Turn a body of one or more forms into a single expression, sequencing
+/// several with `begin`. Defines in a body bind in the frame of the call, so
+/// they are local to it.
+fn sequence(forms: &[Expr]) -> Expr {
+    match forms {
+        [form] => form.clone(),
+        _ => {
+            let mut seq = vec![Expr::Symbol(symbol::BEGIN)];
+            seq.extend_from_slice(forms);
+            Expr::List(seq)
+        }
+    }
+}
+
+fn parse_syntax_rules(name: &str, rules: &[Expr]) -> Option<Macro> {
+    let [Expr::Symbol(keyword), Expr::List(literals), rules @ ..] = rules else {
+        return None;
+    };
+    if *keyword != symbol::SYNTAX_RULES {
+        return None;
+    }
+    let literals = literals
+        .iter()
+        .map(|literal| match literal {
+            Expr::Symbol(s) => Some(*s),
+            _ => None,
+        })
+        .collect::<Option<Vec<Symbol>>>()?;
+    let rules = rules
+        .iter()
+        .map(|rule| match rule {
+            Expr::List(rule) => match rule.as_slice() {
+                [pattern @ Expr::List(_), template] => Some((pattern.clone(), template.clone())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Option<Vec<(Expr, Expr)>>>()?;
+    Some(Macro {
+        name: name.to_string(),
+        literals,
+        rules,
+    })
+}
+
+/// `(define-record-type name (constructor field ...) predicate
+/// (field accessor [modifier]) ...)`, with `form` the operands. Binds the
+/// generated procedures in `env`.
+fn define_record_type(form: &[Expr], expr: &Expr, env: &mut Env) -> Result<Value, Box<EvalError>> {
+    let invalid = |desc: &str| {
+        Box::new(EvalError::InvalidSyntax {
+            expr: expr.clone(),
+            desc: desc.to_string(),
+        })
+    };
+    let [
+        Expr::Symbol(type_name),
+        Expr::List(constructor),
+        Expr::Symbol(predicate),
+        specs @ ..,
+    ] = form
+    else {
+        return Err(invalid(
+            "define-record-type requires a name, a constructor and a predicate",
+        ));
+    };
+    let mut fields = Vec::new();
+    let mut procs = Vec::new();
+    for (index, spec) in specs.iter().enumerate() {
+        let spec = match spec {
+            Expr::List(spec) => spec.as_slice(),
+            _ => &[],
+        };
+        let (field, accessor, modifier) = match spec {
+            [Expr::Symbol(field), Expr::Symbol(accessor)] => (field, accessor, None),
+            [
+                Expr::Symbol(field),
+                Expr::Symbol(accessor),
+                Expr::Symbol(modifier),
+            ] => (field, accessor, Some(modifier)),
+            _ => return Err(invalid("record fields must be (field accessor [modifier])")),
+        };
+        fields.push(field.to_string());
+        procs.push((accessor, RecordProcKind::Accessor(index)));
+        if let Some(modifier) = modifier {
+            procs.push((modifier, RecordProcKind::Modifier(index)));
+        }
+    }
+    let Some((Expr::Symbol(constructor), args)) = constructor.split_first() else {
+        return Err(invalid("record constructor must be (name field ...)"));
+    };
+    let args = args
+        .iter()
+        .map(|arg| match arg {
+            Expr::Symbol(arg) => fields.iter().position(|field| field == arg.as_str()),
+            _ => None,
+        })
+        .collect::<Option<Vec<usize>>>()
+        .ok_or_else(|| invalid("record constructor arguments must be fields of the record"))?;
+    procs.push((constructor, RecordProcKind::Constructor(args)));
+    procs.push((predicate, RecordProcKind::Predicate));
+    let record_type = Rc::new(RecordType {
+        name: type_name.to_string(),
+        fields,
+    });
+    for (name, kind) in procs {
+        let proc = RecordProc {
+            name: name.to_string(),
+            record_type: Rc::clone(&record_type),
+            kind,
+        };
+        env.define(name, Value::RecordProc(Rc::new(proc)));
+    }
+    Ok(Value::Unspecified)
+}
+
+/// Call a procedure generated by `define-record-type`.
+fn apply_record_proc(
+    proc: &RecordProc,
+    args: Vec<Value>,
+    expr: &Expr,
+) -> Result<Value, Box<EvalError>> {
+    let expected = match &proc.kind {
+        RecordProcKind::Constructor(fields) => fields.len(),
+        RecordProcKind::Predicate | RecordProcKind::Accessor(_) => 1,
+        RecordProcKind::Modifier(_) => 2,
+    };
+    if args.len() != expected {
+        return Err(Box::new(EvalError::ArityMismatch {
+            name: Some(proc.name.clone()),
+            min: expected,
+            max: Some(expected),
+            found: args.len(),
+            in_expr: expr.clone(),
+        }));
+    }
+    let instance = match &args[..] {
+        [
+            Value::Record {
+                record_type,
+                fields,
+            },
+            ..,
+        ] if Rc::ptr_eq(record_type, &proc.record_type) => Some(fields),
+        _ => None,
+    };
+    match &proc.kind {
+        RecordProcKind::Constructor(indices) => {
+            let mut fields = vec![Value::Unspecified; proc.record_type.fields.len()];
+            for (&index, arg) in indices.iter().zip(args) {
+                fields[index] = arg;
+            }
+            Ok(Value::Record {
+                record_type: Rc::clone(&proc.record_type),
+                fields: Rc::new(RefCell::new(fields)),
+            })
+        }
+        RecordProcKind::Predicate => Ok(Value::Bool(instance.is_some())),
+        RecordProcKind::Accessor(index) | RecordProcKind::Modifier(index) => {
+            let Some(fields) = instance else {
+                return Err(Box::new(EvalError::TypeError {
+                    expected: proc.record_type.name.clone(),
+                    found: args[0].clone(),
+                    in_expr: expr.clone(),
+                }));
+            };
+            if matches!(proc.kind, RecordProcKind::Modifier(_)) {
+                fields.borrow_mut()[*index] = args[1].clone();
+                Ok(Value::Unspecified)
+            } else {
+                Ok(fields.borrow()[*index].clone())
+            }
+        }
+    }
+}
+
+/// The parameters of a `lambda`, either a list or a single symbol that
+/// collects all the arguments, as in `(lambda args body)`.
+fn lambda_params(
+    params: &Expr,
+    expr: &Expr,
+) -> Result<(Vec<Symbol>, Option<Symbol>), Box<EvalError>> {
+    match params {
+        Expr::List(l) => parse_params(l, expr),
+        Expr::Symbol(args) => Ok((Vec::new(), Some(*args))),
+        _ => Err(Box::new(EvalError::InvalidSyntax {
+            expr: expr.clone(),
+            desc: "lambda parameters must be a list".to_string(),
+        })),
+    }
+}
+
+/// The first `case-lambda` clause that takes `count` arguments.
+fn select_clause<'a>(
+    clauses: &'a [UserFunction],
+    count: usize,
+    expr: &Expr,
+) -> Result<&'a UserFunction, Box<EvalError>> {
+    clauses
+        .iter()
+        .find(|clause| match clause.rest {
+            Some(_) => count >= clause.params.len(),
+            None => count == clause.params.len(),
+        })
+        .ok_or_else(|| {
+            // the arity of the clause nearest to the call
+            let expected = clauses
+                .iter()
+                .map(|clause| clause.params.len())
+                .min_by_key(|len| len.abs_diff(count))
+                .unwrap_or(0);
+            Box::new(EvalError::ArityMismatch {
+                name: None,
+                min: expected,
+                max: Some(expected),
+                found: count,
+                in_expr: expr.clone(),
+            })
+        })
+}
+
+/// Parse a parameter list, where `(a b . rest)` collects the arguments after
+/// `a` and `b` into `rest`.
+fn parse_params(
+    params: &[Expr],
+    expr: &Expr,
+) -> Result<(Vec<Symbol>, Option<Symbol>), Box<EvalError>> {
+    let invalid = |desc: &str| {
+        Box::new(EvalError::InvalidSyntax {
+            expr: expr.clone(),
+            desc: desc.to_string(),
+        })
+    };
+    let (required, rest) = match params.iter().position(|p| *p == Expr::Symbol(symbol::DOT)) {
+        Some(dot) => match &params[dot + 1..] {
+            [Expr::Symbol(rest)] => (&params[..dot], Some(*rest)),
+            _ => {
+                return Err(invalid(
+                    "a dotted parameter list needs one name after the dot",
+                ));
+            }
+        },
+        None => (params, None),
+    };
+    let required = required
+        .iter()
+        .map(|p| match p {
+            Expr::Symbol(s) => Ok(*s),
+            _ => Err(invalid("parameters must be symbols")),
+        })
+        .collect::<Result<Vec<_>, Box<EvalError>>>()?;
+    Ok((required, rest))
+}
+
+/// The frame a call to `func` evaluates its body in: the captured
+/// environment extended with the parameters bound to `args`.
+fn bind_params(func: &UserFunction, args: Vec<Value>, expr: &Expr) -> Result<Env, Box<EvalError>> {
+    let arity_ok = match func.rest {
+        Some(_) => args.len() >= func.params.len(),
+        None => args.len() == func.params.len(),
+    };
+    if !arity_ok {
+        return Err(Box::new(EvalError::ArityMismatch {
+            name: func.name.clone(),
+            min: func.params.len(),
+            max: func.rest.is_none().then_some(func.params.len()),
+            found: args.len(),
+            in_expr: expr.clone(),
+        }));
+    }
+    let mut local_env = Env::child_of(Rc::clone(&func.env));
+    let mut args = args.into_iter();
+    for (name, val) in func.params.iter().zip(args.by_ref()) {
+        local_env.define(name, val);
+    }
+    if let Some(rest) = &func.rest {
+        local_env.define(rest, Value::list(args.collect()));
+    }
+    Ok(local_env)
+}
+
+/// How a call to `func` shows up in backtraces.
+fn frame_name(func: &UserFunction) -> &str {
+    func.name.as_deref().unwrap_or("lambda")
+}
+
+/// Like `eval`, failing with `EvalError::Timeout` if evaluation takes
+/// longer than `timeout`. The clock is checked at every step, so a builtin
+/// that runs long on its own isn't interrupted until it returns. Exception
+/// handlers can't catch the timeout.
+pub fn eval_with_timeout(
+    expr: &Expr,
+    env: &mut Env,
+    timeout: Duration,
+) -> Result<Value, Box<EvalError>> {
+    let outer = env.deadline.get();
+    let deadline = Instant::now() + timeout;
+    // a timeout nested in another can't outlast it
+    if outer.is_none_or(|(outer, _)| deadline < outer) {
+        env.deadline.set(Some((deadline, timeout)));
+    }
+    let result = eval(expr, env);
+    env.deadline.set(outer);
+    result
+}
+
+/// Counts one level of `eval` nesting for as long as it is alive.
+struct DepthGuard(Rc<Cell<usize>>);
+
+impl DepthGuard {
+    fn enter(env: &Env) -> Result<Self, Box<EvalError>> {
+        let depth = env.depth.get() + 1;
+        // the address of a local tells how far down the native stack this
+        // level is, stacks growing downwards
+        let here = &depth as *const usize as usize;
+        if depth == 1 {
+            env.stack_base.set(here);
+        }
+        let stack_used = env.stack_base.get().saturating_sub(here);
+        if depth > env.max_depth || stack_used > env.max_stack {
+            return Err(Box::new(EvalError::OtherError(
+                "maximum recursion depth exceeded".to_string(),
+            )));
+        }
+        env.depth.set(depth);
+        Ok(DepthGuard(env.depth.clone()))
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
+/// Call `func` with already evaluated `args`; `expr` is the calling form,
+/// used for error reporting. Builtins use this to call back into procedures.
+pub fn apply(
+    func: Value,
+    args: Vec<Value>,
+    expr: &Expr,
+    env: &mut Env,
+) -> Result<Value, Box<EvalError>> {
+    match func {
+        Value::BuiltinFunction(builtin) => {
+            let count = args.len();
+            if count < builtin.min_args || builtin.max_args.is_some_and(|max| count > max) {
+                return Err(Box::new(EvalError::ArityMismatch {
+                    name: Some(builtin.name),
+                    min: builtin.min_args,
+                    max: builtin.max_args,
+                    found: count,
+                    in_expr: expr.clone(),
+                }));
+            }
+            (builtin.func)(args, expr.clone(), env)
+        }
+        Value::Continuation(k) => {
+            let value = match <[Value; 1]>::try_from(args) {
+                Ok([value]) => value,
+                Err(args) => {
+                    return Err(Box::new(EvalError::ArityMismatch {
+                        name: None,
+                        min: 1,
+                        max: Some(1),
+                        found: args.len(),
+                        in_expr: expr.clone(),
+                    }));
+                }
+            };
+            Err(Box::new(EvalError::Escape { id: k.id, value }))
+        }
+        Value::Parameter(param) => {
+            if !args.is_empty() {
+                return Err(Box::new(EvalError::ArityMismatch {
+                    name: None,
+                    min: 0,
+                    max: Some(0),
+                    found: args.len(),
+                    in_expr: expr.clone(),
+                }));
+            }
+            Ok(param.borrow().clone())
+        }
+        Value::RecordProc(proc) => apply_record_proc(&proc, args, expr),
+        Value::CaseLambda(clauses) => {
+            let func = select_clause(&clauses, args.len(), expr)?.clone();
+            apply(Value::Function(func), args, expr, env)
+        }
+        Value::Function(func) => {
+            let mut local_env = bind_params(&func, args, expr)?;
+            eval(&func.body, &mut local_env)
+                .map_err(|e| e.in_function(frame_name(&func).to_string()))
+        }
+        _ => Err(Box::new(EvalError::TypeError {
+            expected: "function".to_string(),
+            found: func,
+            in_expr: expr.clone(),
+        })),
+    }
+}
+
+/// Convert a datum into the value it denotes when quoted.
+pub fn quote(expr: &Expr) -> Value {
+    match expr {
+        Expr::Symbol(s) => Value::Symbol(s.to_string()),
+        Expr::Bool(b) => Value::Bool(*b),
+        Expr::Int(n) => Value::Int(*n),
+        Expr::Number(n) => Value::Number(*n),
+        Expr::Rational { num, den } => Value::Rational {
+            num: *num,
+            den: *den,
+        },
+        Expr::String(s) => Value::String(s.clone()),
+        Expr::Char(c) => Value::Char(*c),
+        Expr::List(l) => {
+            let (items, tail) = split_dotted(l);
+            Value::list_with_tail(
+                items.iter().map(quote).collect(),
+                tail.map_or(Value::Nil, quote),
+            )
+        }
+        Expr::Vector(v) => Value::Vector(Rc::new(RefCell::new(v.iter().map(quote).collect()))),
+    }
+}
+
+/// Split a list read as `(items... . tail)` into its items and tail. The
+/// reader only lets the dot through right before the last item.
+pub fn split_dotted(list: &[Expr]) -> (&[Expr], Option<&Expr>) {
+    match list {
+        [items @ .., Expr::Symbol(dot), tail] if *dot == symbol::DOT => (items, Some(tail)),
+        _ => (list, None),
+    }
+}
+
+/// Convert data back into the expression it would be read as, the inverse of
+/// `quote`. A dotted tail comes back as `. tail`, the way it is written.
+/// Values with no written form, like procedures, are an error.
+pub fn datum_to_expr(val: &Value) -> Result<Expr, Box<EvalError>> {
+    match val {
+        Value::Symbol(s) => Ok(Expr::Symbol(Symbol::new(s))),
+        Value::Bool(b) => Ok(Expr::Bool(*b)),
+        Value::Int(n) => Ok(Expr::Int(*n)),
+        Value::Number(n) => Ok(Expr::Number(*n)),
+        Value::Rational { num, den } => Ok(Expr::Rational {
+            num: *num,
+            den: *den,
+        }),
+        Value::String(s) => Ok(Expr::String(s.clone())),
+        Value::Char(c) => Ok(Expr::Char(*c)),
+        Value::Nil => Ok(Expr::List(Vec::new())),
+        Value::Pair(_) => {
+            let mut items = Vec::new();
+            let mut cur = val;
+            while let Value::Pair(pair) = cur {
+                items.push(datum_to_expr(&pair.0)?);
+                cur = &pair.1;
+            }
+            if !matches!(cur, Value::Nil) {
+                items.push(Expr::Symbol(symbol::DOT));
+                items.push(datum_to_expr(cur)?);
+            }
+            Ok(Expr::List(items))
+        }
+        Value::Vector(v) => Ok(Expr::Vector(
+            v.borrow()
+                .iter()
+                .map(datum_to_expr)
+                .collect::<Result<_, _>>()?,
+        )),
+        _ => Err(Box::new(EvalError::OtherError(format!(
+            "{} has no written form to evaluate",
+            val.type_name()
+        )))),
+    }
+}
+
+/// Build the value of a quasiquoted template. `depth` counts the enclosing
+/// quasiquotes, only an unquote at depth 1 is evaluated.
+fn quasiquote(expr: &Expr, depth: usize, env: &mut Env) -> Result<Value, Box<EvalError>> {
+    let list = match expr {
+        Expr::List(list) => list,
+        _ => return Ok(quote(expr)),
+    };
+    if let [Expr::Symbol(s), arg] = list.as_slice() {
+        match *s {
+            symbol::UNQUOTE if depth == 1 => return eval(arg, env),
+            symbol::UNQUOTE | symbol::UNQUOTE_SPLICING => {
+                return Ok(Value::list(vec![
+                    Value::Symbol(s.to_string()),
+                    quasiquote(arg, depth - 1, env)?,
+                ]));
+            }
+            symbol::QUASIQUOTE => {
+                return Ok(Value::list(vec![
+                    Value::Symbol(s.to_string()),
+                    quasiquote(arg, depth + 1, env)?,
+                ]));
+            }
+            _ => {}
+        }
+    }
+    let (list, tail) = split_dotted(list);
+    let mut items = Vec::new();
+    for item in list {
+        if let Expr::List(l) = item
+            && let [Expr::Symbol(s), arg] = l.as_slice()
+            && *s == symbol::UNQUOTE_SPLICING
+            && depth == 1
+        {
+            let val = eval(arg, env)?;
+            match val.list_to_vec() {
+                Some(spliced) => items.extend(spliced),
+                None => {
+                    return Err(Box::new(EvalError::TypeError {
+                        expected: "List".to_string(),
+                        found: val,
+                        in_expr: item.clone(),
+                    }));
+                }
+            }
+        } else {
+            items.push(quasiquote(item, depth, env)?);
         }
     }
+    let tail = match tail {
+        Some(tail) => quasiquote(tail, depth, env)?,
+        None => Value::Nil,
+    };
+    Ok(Value::list_with_tail(items, tail))
 }
 
 #[derive(Debug, Clone)]
@@ -285,9 +1367,595 @@ pub enum EvalError {
         in_expr: Expr,
     },
     ArityMismatch {
-        expected: usize,
+        /// The procedure called, if it has a name.
+        name: Option<String>,
+        min: usize,
+        /// `None` if any number of arguments from `min` on is accepted.
+        max: Option<usize>,
         found: usize,
         in_expr: Expr,
     },
+    ParseError(ParseError),
+    FileError {
+        path: String,
+        desc: String,
+    },
+    UserError {
+        message: String,
+        irritants: Vec<Value>,
+    },
     OtherError(String),
+    /// `eval_with_timeout` ran out of the time it was given.
+    Timeout(Duration),
+    /// Evaluation took all the steps `Env::set_fuel` allowed.
+    OutOfFuel,
+    /// A value passed to `raise` that no handler caught.
+    Raise(Value),
+    /// An error raised inside user function calls, with the functions that
+    /// were being applied, innermost first.
+    Backtrace {
+        error: Box<EvalError>,
+        frames: Vec<String>,
+    },
+    /// Not an error: a continuation was called with `value`, unwinding to the
+    /// `call/cc` that made continuation `id`.
+    Escape {
+        id: usize,
+        value: Value,
+    },
+    /// An error with where in the source it happened.
+    Located {
+        error: Box<EvalError>,
+        span: Span,
+    },
+}
+
+impl EvalError {
+    /// Record that the error propagated out of a call to `name`.
+    pub fn in_function(mut self: Box<Self>, name: String) -> Box<EvalError> {
+        match self.as_mut() {
+            EvalError::Backtrace { frames, .. } => {
+                frames.push(name);
+                return self;
+            }
+            // `call/cc` looks for the escape itself, not under a backtrace
+            EvalError::Escape { .. } => return self,
+            _ => {}
+        }
+        Box::new(EvalError::Backtrace {
+            error: self,
+            frames: vec![name],
+        })
+    }
+
+    /// Point the error at the part of the top-level `form` it was raised
+    /// for, or at `form` itself when that part came from elsewhere, e.g. the
+    /// body of a function defined earlier. `spans` are those `form` was
+    /// parsed with.
+    pub fn locate(self: Box<Self>, form: &Expr, spans: &SpanTree) -> Box<EvalError> {
+        let culprit = match self.innermost() {
+            // already located, e.g. inside a loaded file
+            EvalError::Located { .. } => return self,
+            EvalError::UnboundSymbol(s) => Some(Expr::Symbol(Symbol::new(s))),
+            EvalError::InvalidSyntax { expr, .. } => Some(expr.clone()),
+            EvalError::TypeError { in_expr, .. } | EvalError::ArityMismatch { in_expr, .. } => {
+                Some(in_expr.clone())
+            }
+            _ => None,
+        };
+        let span = culprit
+            .and_then(|culprit| spans.find(form, &culprit))
+            .unwrap_or(spans.span);
+        Box::new(EvalError::Located { error: self, span })
+    }
+
+    /// The value an exception handler receives for the error: what was
+    /// passed to `raise`, or the error itself as a condition.
+    pub fn into_condition(self) -> Value {
+        match self {
+            EvalError::Raise(val) => val,
+            EvalError::Backtrace { error, .. } | EvalError::Located { error, .. } => {
+                (*error).into_condition()
+            }
+            error => Value::Condition(Rc::new(error)),
+        }
+    }
+
+    /// The error that raising `condition` signals, the inverse of
+    /// `into_condition`.
+    pub fn from_condition(condition: Value) -> EvalError {
+        match condition {
+            Value::Condition(e) => (*e).clone(),
+            val => EvalError::Raise(val),
+        }
+    }
+
+    /// Whether exception handlers may catch the error. Escapes, timeouts and
+    /// running out of fuel unwind past every handler.
+    pub(crate) fn is_catchable(&self) -> bool {
+        match self {
+            EvalError::Backtrace { error, .. } | EvalError::Located { error, .. } => {
+                error.is_catchable()
+            }
+            EvalError::Escape { .. } | EvalError::Timeout(_) | EvalError::OutOfFuel => false,
+            _ => true,
+        }
+    }
+
+    /// The error under any backtrace.
+    fn innermost(&self) -> &EvalError {
+        match self {
+            EvalError::Backtrace { error, .. } => error.innermost(),
+            _ => self,
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::UnboundSymbol(s) => write!(f, "Unbound symbol: {s}"),
+            EvalError::InvalidSyntax { expr, desc } => {
+                write!(f, "Invalid syntax: {desc} in {expr}")
+            }
+            EvalError::TypeError {
+                expected,
+                found,
+                in_expr,
+            } => write!(
+                f,
+                "Type error: expected {expected}, found {found} in {in_expr}"
+            ),
+            EvalError::ArityMismatch {
+                name,
+                min,
+                max,
+                found,
+                in_expr,
+            } => {
+                write!(f, "Arity mismatch: ")?;
+                if let Some(name) = name {
+                    write!(f, "{name} ")?;
+                }
+                match max {
+                    Some(1) if *min == 1 => write!(f, "expected 1 argument")?,
+                    Some(max) if max == min => write!(f, "expected {min} arguments")?,
+                    Some(max) if *max == min + 1 => write!(f, "expected {min} or {max} arguments")?,
+                    Some(max) => write!(f, "expected {min} to {max} arguments")?,
+                    None if *min == 1 => write!(f, "expected at least 1 argument")?,
+                    None => write!(f, "expected at least {min} arguments")?,
+                }
+                write!(f, ", found {found} in {in_expr}")
+            }
+            EvalError::ParseError(e) => write!(f, "Parse error: {e}"),
+            EvalError::FileError { path, desc } => write!(f, "Cannot load {path}: {desc}"),
+            EvalError::UserError { message, irritants } => {
+                write!(f, "{message}")?;
+                for irritant in irritants {
+                    write!(f, " {irritant}")?;
+                }
+                Ok(())
+            }
+            EvalError::OtherError(s) => write!(f, "{s}"),
+            EvalError::Timeout(timeout) => write!(f, "Evaluation timed out after {timeout:?}"),
+            EvalError::OutOfFuel => write!(f, "out of fuel"),
+            EvalError::Raise(val) => write!(f, "Uncaught exception: {val}"),
+            EvalError::Escape { .. } => {
+                write!(f, "continuation called after its call/cc returned")
+            }
+            EvalError::Located { error, span } => write!(f, "{span}: {error}"),
+            EvalError::Backtrace { error, frames } => {
+                write!(f, "{error}")?;
+                for name in frames.iter().take(BACKTRACE_LIMIT) {
+                    write!(f, "\n  in {name}")?;
+                }
+                if frames.len() > BACKTRACE_LIMIT {
+                    write!(f, "\n  ... {} more", frames.len() - BACKTRACE_LIMIT)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EvalError::ParseError(e) => Some(e),
+            EvalError::Backtrace { error, .. } | EvalError::Located { error, .. } => error.source(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Tokenizer, parse_program, parse_program_spanned};
+
+    fn run(source: &str, env: &mut Env) -> Result<Value, Box<EvalError>> {
+        let mut last = Value::Unspecified;
+        for expr in &parse_program(&mut Tokenizer::new(source).peekable()).unwrap() {
+            last = eval(expr, env)?;
+        }
+        Ok(last)
+    }
+
+    /// The printed value of `source` run in a fresh environment, or the
+    /// error message.
+    fn show(source: &str) -> String {
+        colored::control::set_override(false);
+        match run(source, &mut Env::new()) {
+            Ok(val) => val.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn infinite_recursion_is_an_error() {
+        let mut env = Env::new();
+        let err = run("(define (f n) (+ 1 (f n))) (f 0)", &mut env).unwrap_err();
+        let EvalError::Backtrace { error, .. } = *err else {
+            panic!("expected a backtrace, got {err:?}");
+        };
+        assert!(
+            matches!(*error, EvalError::OtherError(ref msg) if msg == "maximum recursion depth exceeded")
+        );
+        // the environment is still usable afterwards
+        assert!(matches!(run("(+ 1 2)", &mut env), Ok(Value::Int(3))));
+    }
+
+    #[test]
+    fn max_depth_is_configurable() {
+        let define = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))";
+        let mut env = Env::new();
+        run(define, &mut env).unwrap();
+        assert!(matches!(run("(f 3)", &mut env), Ok(Value::Int(3))));
+        let mut env = Env::new();
+        env.max_depth = 5;
+        run(define, &mut env).unwrap();
+        assert!(run("(f 3)", &mut env).is_err());
+    }
+
+    fn backtrace(source: &str) -> Vec<String> {
+        match *run(source, &mut Env::new()).unwrap_err() {
+            EvalError::Backtrace { frames, .. } => frames,
+            err => panic!("expected a backtrace, got {err:?}"),
+        }
+    }
+
+    #[test]
+    fn backtrace_names_the_caller_chain() {
+        let defs = "(define (c x) (car x)) (define (a x) (b x))";
+        // `b` calls `c` in tail position, then not
+        assert_eq!(
+            backtrace(&format!("{defs} (define (b x) (c x)) (a 1)")),
+            ["c", "b", "a"]
+        );
+        assert_eq!(
+            backtrace(&format!("{defs} (define (b x) (+ 1 (c x))) (list (a 1))")),
+            ["c", "b", "a"]
+        );
+        // a loop is one frame however many times it went around
+        assert_eq!(
+            backtrace("(define (loop n) (if (= n 0) (car n) (loop (- n 1)))) (loop 100)"),
+            ["loop"]
+        );
+    }
+
+    /// Where the first error in `source` is reported at.
+    fn error_span(source: &str) -> Span {
+        let mut env = Env::new();
+        for (expr, spans) in &parse_program_spanned(&mut Tokenizer::new(source).peekable()).unwrap()
+        {
+            if let Err(e) = eval(expr, &mut env) {
+                match *e.locate(expr, spans) {
+                    EvalError::Located { span, .. } => return span,
+                    e => panic!("expected a location, got {e:?}"),
+                }
+            }
+        }
+        panic!("expected an error")
+    }
+
+    #[test]
+    fn errors_point_into_multi_line_forms() {
+        let source = "(define x 5)\n(let ((y 1))\n  (list y\n        (+ x\n           (car x))))";
+        assert_eq!(
+            error_span(source),
+            Span {
+                line: 5,
+                column: 12
+            }
+        );
+        let source = "(begin\n  (display \"\")\n  (list 1 (g 2)))";
+        assert_eq!(
+            error_span(source),
+            Span {
+                line: 3,
+                column: 12
+            }
+        );
+    }
+
+    #[test]
+    fn bindings_shadow_special_forms() {
+        let mut env = Env::new();
+        assert!(matches!(run("(if #t 1 2)", &mut env), Ok(Value::Int(1))));
+        assert!(matches!(
+            run("(define (f if) (if 1 2)) (f +)", &mut env),
+            Ok(Value::Int(3))
+        ));
+        // only where the binding is visible
+        assert!(matches!(run("(if #t 1 2)", &mut env), Ok(Value::Int(1))));
+        assert!(matches!(
+            run("(define (if a b c) 42) (if #t 1 2)", &mut env),
+            Ok(Value::Int(42))
+        ));
+    }
+
+    #[test]
+    fn named_let() {
+        assert_eq!(
+            show("(let loop ((i 0) (sum 0)) (if (> i 100) sum (loop (+ i 1) (+ sum i))))"),
+            "5050"
+        );
+        // a tail call, so a long loop runs in constant stack
+        assert_eq!(
+            show("(let loop ((i 0)) (if (= i 10000) i (loop (+ i 1))))"),
+            "10000"
+        );
+    }
+
+    #[test]
+    fn syntax_rules_macros() {
+        let swap = "(define-syntax swap!
+              (syntax-rules () ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))";
+        assert_eq!(
+            show(&format!(
+                "{swap} (define x 1) (define y 2) (swap! x y) (list x y)"
+            )),
+            "(2 1)"
+        );
+        let my_if = "(define-syntax my-if (syntax-rules (then else)
+                       ((_ c then t else e) (cond (c t) (else e)))))";
+        assert_eq!(show(&format!("{my_if} (my-if #t then 1 else 2)")), "1");
+        assert_eq!(show(&format!("{my_if} (my-if #f then 1 else 2)")), "2");
+        // only the taken branch is evaluated
+        assert_eq!(
+            show(&format!("{my_if} (my-if #t then 1 else (car '()))")),
+            "1"
+        );
+        let my_list = "(define-syntax my-list (syntax-rules () ((_ x ...) (list x ...))))";
+        assert_eq!(show(&format!("{my_list} (my-list 1 (+ 1 1) 3)")), "(1 2 3)");
+        assert_eq!(show(&format!("{my_list} (my-list)")), "nil");
+    }
+
+    #[test]
+    fn set_of_unbound_symbol_is_an_error() {
+        assert!(show("(set! nope 1)").contains("Unbound symbol: nope"));
+        let mut env = Env::new();
+        assert!(run("(set! nope 1)", &mut env).is_err());
+        assert!(!env.is_bound("nope"));
+        assert_eq!(show("(define x 1) (set! x 2) x"), "2");
+    }
+
+    #[test]
+    fn rest_parameters() {
+        let f = "(define (f a . rest) (list a rest))";
+        assert_eq!(show(&format!("{f} (f 1)")), "(1 nil)");
+        assert_eq!(show(&format!("{f} (f 1 2 3)")), "(1 (2 3))");
+        assert!(show(&format!("{f} (f)")).contains("expected at least 1 argument"));
+        assert_eq!(show("((lambda args args) 1 2)"), "(1 2)");
+        assert_eq!(show("((lambda args args))"), "nil");
+        assert_eq!(show("((lambda (a b . c) c) 1 2 3 4)"), "(3 4)");
+    }
+
+    #[test]
+    fn delay_runs_its_body_once() {
+        let source = "(define n 0)
+            (define p (delay (begin (set! n (+ n 1)) 'v)))
+            (list (force p) (force p) n)";
+        assert_eq!(show(source), "(v v 1)");
+    }
+
+    #[test]
+    fn call_cc_escapes_early() {
+        let source = "(call/cc (lambda (k)
+              (for-each (lambda (x) (if (> x 2) (k x) #f)) '(1 2 3 4))
+              'none))";
+        assert_eq!(show(source), "3");
+        assert_eq!(show("(+ 1 (call/cc (lambda (k) 10)))"), "11");
+    }
+
+    #[test]
+    fn exception_handlers_catch_errors() {
+        assert_eq!(
+            show("(with-exception-handler (lambda (e) 'caught) (lambda () (/ 1 0)))"),
+            "caught"
+        );
+        assert_eq!(
+            show(
+                "(with-exception-handler (lambda (e) (error-object-message e)) (lambda () (/ 1 0)))"
+            ),
+            "\"Division by zero in (/ 1 0)\""
+        );
+        assert_eq!(
+            show("(with-exception-handler (lambda (e) e) (lambda () (raise 'oops)))"),
+            "oops"
+        );
+    }
+
+    #[test]
+    fn guard_catches_division_by_zero() {
+        assert_eq!(
+            show("(guard (e ((error-object? e) (error-object-message e))) (/ 1 0))"),
+            "\"Division by zero in (/ 1 0)\""
+        );
+        assert_eq!(
+            show("(guard (e ((symbol? e) (list 'got e))) (raise 'oops))"),
+            "(got oops)"
+        );
+        assert!(
+            show("(guard (e ((string? e) e)) (raise 'oops))").contains("Uncaught exception: oops")
+        );
+        assert_eq!(show("(guard (e (#t 'caught)) 42)"), "42");
+    }
+
+    #[test]
+    fn do_loops() {
+        assert_eq!(
+            show("(do ((i 0 (+ i 1)) (sum 0 (+ sum i))) ((= i 100) sum))"),
+            "4950"
+        );
+        let body = "(define acc '()) (do ((i 0 (+ i 1))) ((= i 3) acc) (set! acc (cons i acc)))";
+        assert_eq!(show(body), "(2 1 0)");
+    }
+
+    #[test]
+    fn deep_and_or_recursion_stays_in_tail_position() {
+        let source = "(define (f n) (or (= n 0) (f (- n 1)))) (f 10000)";
+        assert_eq!(show(source), "#t");
+        let source = "(define (g n) (and (>= n 0) (if (= n 0) 'done (g (- n 1))))) (g 10000)";
+        assert_eq!(show(source), "done");
+    }
+
+    #[test]
+    fn parameterize_restores_after_an_exception() {
+        let source = "(define p (make-parameter 1))
+            (define inside (guard (e (#t (p))) (parameterize ((p 2)) (raise 'x))))
+            (list inside (p))";
+        assert_eq!(show(source), "(1 1)");
+        assert_eq!(
+            show("(define p (make-parameter 1)) (parameterize ((p 2)) (p))"),
+            "2"
+        );
+    }
+
+    #[test]
+    fn multiple_values() {
+        assert_eq!(show("(call-with-values (lambda () (values)) list)"), "nil");
+        assert_eq!(
+            show("(call-with-values (lambda () (values 1)) list)"),
+            "(1)"
+        );
+        assert_eq!(
+            show("(call-with-values (lambda () (values 1 2)) list)"),
+            "(1 2)"
+        );
+        assert_eq!(show("(call-with-values (lambda () 5) list)"), "(5)");
+        assert_eq!(show("(+ 1 (values 2))"), "3");
+    }
+
+    #[test]
+    fn record_types() {
+        let point = "(define-record-type point (make-point x y) point?
+              (x point-x set-point-x!) (y point-y))
+            (define pt (make-point 1 2))";
+        assert_eq!(
+            show(&format!(
+                "{point} (list (point? pt) (point? 5) (point-x pt) (point-y pt))"
+            )),
+            "(#t #f 1 2)"
+        );
+        assert_eq!(
+            show(&format!("{point} (set-point-x! pt 10) (point-x pt)")),
+            "10"
+        );
+        assert_eq!(show(&format!("{point} pt")), "#<point>");
+        assert!(show(&format!("{point} (point-x 5)")).contains("Type error"));
+    }
+
+    #[test]
+    fn case_lambda_dispatches_on_arity() {
+        let f = "(define f (case-lambda
+              ((x) (list 'one x)) ((x y) (list 'two x y)) ((x . r) (list 'many r))))";
+        assert_eq!(
+            show(&format!("{f} (list (f 1) (f 1 2) (f 1 2 3))")),
+            "((one 1) (two 1 2) (many (2 3)))"
+        );
+        assert!(show("((case-lambda ((x) x)))").contains("Arity mismatch"));
+    }
+
+    #[test]
+    fn running_out_of_fuel_cannot_be_caught() {
+        let mut env = Env::new();
+        env.set_fuel(Some(1000));
+        let err = run("(let loop () (loop))", &mut env).unwrap_err();
+        assert!(err.to_string().starts_with("out of fuel"));
+        for source in [
+            "(with-exception-handler (lambda (e) 'caught) (lambda () (let loop () (loop))))",
+            "(with-exception-handler list (lambda () (let loop () (loop))))",
+            "(guard (e (#t 'caught)) (let loop () (loop)))",
+        ] {
+            let mut env = Env::new();
+            env.set_fuel(Some(1000));
+            let err = run(source, &mut env).unwrap_err();
+            assert!(!err.is_catchable(), "{source}: {err}");
+            assert!(err.to_string().starts_with("out of fuel"));
+        }
+    }
+
+    #[test]
+    fn timeouts_cannot_be_caught() {
+        for source in [
+            "(let loop () (loop))",
+            "(with-exception-handler list (lambda () (let loop () (loop))))",
+            "(guard (e (#t 'caught)) (let loop () (loop)))",
+        ] {
+            let mut env = Env::new();
+            let expr = source.parse::<Expr>().unwrap();
+            let err = eval_with_timeout(&expr, &mut env, Duration::from_millis(50)).unwrap_err();
+            assert!(!err.is_catchable(), "{source}: {err}");
+            assert!(
+                err.to_string().starts_with("Evaluation timed out"),
+                "{source}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn body_definitions_stay_local() {
+        assert_eq!(show("(let () (define inner 1) inner)"), "1");
+        assert!(show("(let () (define inner 1) inner) inner").contains("Unbound symbol: inner"));
+        let source =
+            "(define p (make-parameter 1)) (parameterize ((p 2)) (define leak 1) leak) leak";
+        assert!(show(source).contains("Unbound symbol: leak"));
+        let source = "(guard (e (#t 0)) (define leak 1) leak) leak";
+        assert!(show(source).contains("Unbound symbol: leak"));
+    }
+
+    #[test]
+    fn procedures_print_their_name_and_parameters() {
+        assert_eq!(show("(define (named x) x) named"), "#<procedure named (x)>");
+        assert_eq!(
+            show("(define (rest a . more) a) rest"),
+            "#<procedure rest (a . more)>"
+        );
+        assert_eq!(show("(lambda (x) x)"), "#<procedure (x)>");
+    }
+
+    #[test]
+    fn builtin_arity_errors_name_the_builtin() {
+        let err = show("(< 1)");
+        assert!(err.contains("Arity mismatch: < expected"), "{err}");
+        let err = show("(for-each < '(1 2))");
+        assert!(err.contains("Arity mismatch: < expected"), "{err}");
+    }
+
+    #[test]
+    fn top_level_defines_resolve_at_call_time() {
+        let ev = "(define (ev? n) (if (= n 0) #t (od? (- n 1))))";
+        let od = "(define (od? n) (if (= n 0) #f (ev? (- n 1))))";
+        assert_eq!(
+            show(&format!("{ev} {od} (list (ev? 10) (od? 7))")),
+            "(#t #t)"
+        );
+        assert_eq!(
+            show(&format!("{od} {ev} (list (ev? 10001) (od? 7))")),
+            "(#f #t)"
+        );
+        let source = "(define (f) (g)) (define (g) 1) (define a (f)) (define (g) 2) (list a (f))";
+        assert_eq!(show(source), "(1 2)");
+    }
 }
@@ -1,36 +1,584 @@
-use crate::ast::Expr;
+use std::{
+    fmt::Display,
+    io::{self, BufReader, Read},
+    iter::Peekable,
+    str::Chars,
+};
 
-pub fn tokenize(input: &str) -> Vec<String> {
-    input
-        .replace("(", " ( ")
-        .replace(")", " ) ")
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect()
+use crate::{
+    ast::Expr,
+    symbol::{self, Symbol},
+    value::reduce_ratio,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnexpectedEof,
+    UnexpectedToken(String, Span),
+    /// Where the literal's opening quote is.
+    UnterminatedString(Span),
+    /// Reading the source failed, or it isn't UTF-8.
+    Io(io::ErrorKind, String),
 }
 
-pub fn parse(tokens: &mut Vec<String>) -> Expr {
-    if tokens.is_empty() {
-        panic!("Unexpected EOF");
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnexpectedEof => write!(f, "unexpected end of input"),
+            ParseError::UnexpectedToken(t, span) => write!(f, "unexpected token '{t}' at {span}"),
+            ParseError::UnterminatedString(span) => {
+                write!(f, "unterminated string literal starting at {span}")
+            }
+            ParseError::Io(_, message) => write!(f, "{message}"),
+        }
     }
+}
 
-    let token = tokens.remove(0);
-    match token.as_str() {
-        "(" => {
-            let mut list = Vec::new();
-            while tokens[0] != ")" {
-                list.push(parse(tokens));
+impl std::error::Error for ParseError {}
+
+/// A position in source text, both counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// A lexeme of source text. String literals keep their quotes and escapes,
+/// `parse` unescapes them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    /// Where the token starts.
+    pub span: Span,
+}
+
+/// Where a `Tokenizer` reads chars from, e.g. the `Chars` of a string.
+pub trait CharSource {
+    /// The next char, `None` at the end of input.
+    fn next_char(&mut self) -> Result<Option<char>, ParseError>;
+}
+
+impl<I: Iterator<Item = char>> CharSource for I {
+    fn next_char(&mut self) -> Result<Option<char>, ParseError> {
+        Ok(self.next())
+    }
+}
+
+/// The chars of a UTF-8 byte stream, e.g. a file or stdin, read as they are
+/// needed.
+pub struct ReadChars<R> {
+    bytes: io::Bytes<BufReader<R>>,
+}
+
+impl<R: Read> ReadChars<R> {
+    pub fn new(reader: R) -> Self {
+        ReadChars {
+            bytes: BufReader::new(reader).bytes(),
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<Option<u8>, ParseError> {
+        self.bytes
+            .next()
+            .transpose()
+            .map_err(|e| ParseError::Io(e.kind(), e.to_string()))
+    }
+}
+
+impl<R: Read> CharSource for ReadChars<R> {
+    fn next_char(&mut self) -> Result<Option<char>, ParseError> {
+        let invalid = || {
+            ParseError::Io(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8".to_string(),
+            )
+        };
+        let Some(first) = self.next_byte()? else {
+            return Ok(None);
+        };
+        // how many bytes the encoding of the char takes, from its first
+        let len = match first {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Err(invalid()),
+        };
+        let mut buf = [first, 0, 0, 0];
+        for byte in &mut buf[1..len] {
+            *byte = self.next_byte()?.ok_or_else(invalid)?;
+        }
+        let decoded = std::str::from_utf8(&buf[..len]).map_err(|_| invalid())?;
+        Ok(decoded.chars().next())
+    }
+}
+
+/// Lexes tokens lazily from a source of chars, reading no further ahead
+/// than the char after the current token. An unterminated string literal or
+/// a source failing is reported as an error and ends the stream.
+pub struct Tokenizer<S> {
+    chars: S,
+    /// The next char if it was looked at but not consumed yet.
+    peeked: Option<char>,
+    /// A failure of `chars`, reported once the token it cut short is due.
+    error: Option<ParseError>,
+    pos: Span,
+    failed: bool,
+}
+
+impl<'a> Tokenizer<Chars<'a>> {
+    pub fn new(input: &'a str) -> Self {
+        Tokenizer::from_chars(input.chars())
+    }
+}
+
+impl<R: Read> Tokenizer<ReadChars<R>> {
+    /// Lex what `reader` yields, reading it as far as the tokens taken so far.
+    pub fn from_reader(reader: R) -> Self {
+        Tokenizer::from_chars(ReadChars::new(reader))
+    }
+}
+
+impl<S: CharSource> Tokenizer<S> {
+    pub fn from_chars(chars: S) -> Self {
+        Tokenizer {
+            chars,
+            peeked: None,
+            error: None,
+            pos: Span { line: 1, column: 1 },
+            failed: false,
+        }
+    }
+
+    /// The next char without consuming it. A failing source looks like the
+    /// end of input until its error is reported.
+    fn peek(&mut self) -> Option<char> {
+        if self.peeked.is_none() && self.error.is_none() {
+            match self.chars.next_char() {
+                Ok(c) => self.peeked = c,
+                Err(e) => self.error = Some(e),
             }
-            tokens.remove(0);
-            Expr::List(list)
         }
-        ")" => panic!("Unexpected ')'"),
-        _ => {
-            if let Ok(num) = token.parse::<f64>() {
-                Expr::Number(num)
+        self.peeked
+    }
+
+    /// Consume the next char, keeping track of the position.
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.peeked = None;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
+
+    /// The rest of a string literal whose opening quote, at `start`, was
+    /// consumed already.
+    fn string_literal(&mut self, start: Span) -> Result<String, ParseError> {
+        let mut token = "\"".to_string();
+        while let Some(c) = self.bump() {
+            token.push(c);
+            if c == '\\' {
+                if let Some(escaped) = self.bump() {
+                    token.push(escaped);
+                }
+            } else if c == '"' {
+                return Ok(token);
+            }
+        }
+        Err(ParseError::UnterminatedString(start))
+    }
+
+    /// The rest of an atom whose first chars, `token`, were consumed already.
+    fn atom(&mut self, mut token: String) -> String {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || "()'`,\";".contains(c) {
+                break;
+            }
+            token.push(c);
+            self.bump();
+        }
+        token
+    }
+
+    fn lex(&mut self) -> Option<Result<Token, ParseError>> {
+        while let Some(c) = self.peek() {
+            let span = self.pos;
+            self.bump();
+            let text = match c {
+                '(' | ')' | '\'' | '`' => c.to_string(),
+                ',' => {
+                    if self.peek() == Some('@') {
+                        self.bump();
+                        ",@".to_string()
+                    } else {
+                        ",".to_string()
+                    }
+                }
+                ';' => {
+                    // line comment, skip until the end of line
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                '"' => match self.string_literal(span) {
+                    Ok(text) => text,
+                    Err(e) => return Some(Err(e)),
+                },
+                c if c.is_whitespace() => continue,
+                '#' => match self.peek() {
+                    Some('(') => {
+                        self.bump();
+                        "#(".to_string()
+                    }
+                    Some('\\') => {
+                        // a character literal, its first char may be a delimiter
+                        self.bump();
+                        let mut token = "#\\".to_string();
+                        token.extend(self.bump());
+                        self.atom(token)
+                    }
+                    _ => self.atom("#".to_string()),
+                },
+                _ => self.atom(c.to_string()),
+            };
+            return Some(Ok(Token { text, span }));
+        }
+        None
+    }
+}
+
+impl<S: CharSource> Iterator for Tokenizer<S> {
+    type Item = Result<Token, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let token = self.lex();
+        // a token cut short by a failing source isn't worth returning
+        let token = match self.error.take() {
+            Some(e) => Some(Err(e)),
+            None => token,
+        };
+        self.failed = matches!(token, Some(Err(_)));
+        token
+    }
+}
+
+/// Lex all of `input` at once.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    Tokenizer::new(input).collect()
+}
+
+/// Lex everything `reader` yields, e.g. a file or stdin, without reading it
+/// into memory first. Source that isn't UTF-8 or doesn't lex is reported as
+/// `InvalidData`, with the `ParseError` as its inner error in the latter case.
+pub fn tokenize_reader(reader: impl Read) -> io::Result<Vec<Token>> {
+    Tokenizer::from_reader(reader)
+        .collect::<Result<_, _>>()
+        .map_err(|e| match e {
+            ParseError::Io(kind, message) => io::Error::new(kind, message),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        })
+}
+
+/// Where a datum starts and, for lists and vectors, where each of its items
+/// do. It mirrors the shape of the `Expr` it was parsed with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanTree {
+    pub span: Span,
+    pub items: Vec<SpanTree>,
+}
+
+impl SpanTree {
+    /// Where the first occurrence of `target` within `expr` starts, `self`
+    /// being the spans of `expr`.
+    pub fn find(&self, expr: &Expr, target: &Expr) -> Option<Span> {
+        if expr == target {
+            return Some(self.span);
+        }
+        match expr {
+            Expr::List(items) | Expr::Vector(items) => items
+                .iter()
+                .zip(&self.items)
+                .find_map(|(item, spans)| spans.find(item, target)),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse<I>(tokens: &mut Peekable<I>) -> Result<Expr, ParseError>
+where
+    I: Iterator<Item = Result<Token, ParseError>>,
+{
+    parse_spanned(tokens).map(|(expr, _)| expr)
+}
+
+/// Whether a lexed token is `text`, for `Peekable::next_if`.
+fn token_is(text: &str) -> impl Fn(&Result<Token, ParseError>) -> bool + '_ {
+    move |token| matches!(token, Ok(token) if token.text == text)
+}
+
+/// Parse one datum along with where each part of it is in the source,
+/// consuming only the tokens of that datum.
+pub fn parse_spanned<I>(tokens: &mut Peekable<I>) -> Result<(Expr, SpanTree), ParseError>
+where
+    I: Iterator<Item = Result<Token, ParseError>>,
+{
+    let Some(token) = tokens.next().transpose()? else {
+        return Err(ParseError::UnexpectedEof);
+    };
+    let leaf = |expr| {
+        Ok((
+            expr,
+            SpanTree {
+                span: token.span,
+                items: Vec::new(),
+            },
+        ))
+    };
+    match token.text.as_str() {
+        "(" | "#(" => {
+            let mut list = Vec::new();
+            let mut items = Vec::new();
+            while tokens.next_if(token_is(")")).is_none() {
+                // `(items... . tail)`, kept as a `.` symbol before the tail
+                if let Some(Ok(dot)) = tokens.next_if(token_is(".")) {
+                    if token.text == "#(" || list.is_empty() {
+                        return Err(ParseError::UnexpectedToken(dot.text, dot.span));
+                    }
+                    let (tail, spans) = parse_spanned(tokens)?;
+                    match tokens.next().transpose()? {
+                        None => return Err(ParseError::UnexpectedEof),
+                        Some(t) if t.text == ")" => {}
+                        Some(t) => return Err(ParseError::UnexpectedToken(t.text, t.span)),
+                    }
+                    list.extend([Expr::Symbol(symbol::DOT), tail]);
+                    items.extend([
+                        SpanTree {
+                            span: dot.span,
+                            items: Vec::new(),
+                        },
+                        spans,
+                    ]);
+                    break;
+                }
+                let (expr, spans) = parse_spanned(tokens)?;
+                list.push(expr);
+                items.push(spans);
+            }
+            let expr = if token.text == "#(" {
+                Expr::Vector(list)
             } else {
-                Expr::Symbol(token)
+                Expr::List(list)
+            };
+            Ok((
+                expr,
+                SpanTree {
+                    span: token.span,
+                    items,
+                },
+            ))
+        }
+        ")" | "." => Err(ParseError::UnexpectedToken(token.text, token.span)),
+        "'" | "`" | "," | ",@" => {
+            let name = match token.text.as_str() {
+                "'" => symbol::QUOTE,
+                "`" => symbol::QUASIQUOTE,
+                "," => symbol::UNQUOTE,
+                _ => symbol::UNQUOTE_SPLICING,
+            };
+            let (expr, spans) = parse_spanned(tokens)?;
+            let prefix = SpanTree {
+                span: token.span,
+                items: Vec::new(),
+            };
+            Ok((
+                Expr::List(vec![Expr::Symbol(name), expr]),
+                SpanTree {
+                    span: token.span,
+                    items: vec![prefix, spans],
+                },
+            ))
+        }
+        "#t" | "#true" => leaf(Expr::Bool(true)),
+        "#f" | "#false" => leaf(Expr::Bool(false)),
+        t if t.starts_with('"') => leaf(Expr::String(parse_string(&token)?)),
+        t if t.starts_with("#\\") => leaf(Expr::Char(parse_char(&token)?)),
+        // Symbols starting with `#` are reserved, so `gensym`'s can't be read.
+        t if t.starts_with('#') => Err(ParseError::UnexpectedToken(token.text, token.span)),
+        t => leaf(parse_number(t).unwrap_or_else(|| Expr::Symbol(Symbol::new(t)))),
+    }
+}
+
+/// Parse every top-level datum in `tokens`, e.g. the contents of a source file.
+pub fn parse_program<I>(tokens: &mut Peekable<I>) -> Result<Vec<Expr>, ParseError>
+where
+    I: Iterator<Item = Result<Token, ParseError>>,
+{
+    let mut program = Vec::new();
+    while tokens.peek().is_some() {
+        program.push(parse(tokens)?);
+    }
+    Ok(program)
+}
+
+/// Like `parse_program`, keeping the spans of each datum.
+pub fn parse_program_spanned<I>(
+    tokens: &mut Peekable<I>,
+) -> Result<Vec<(Expr, SpanTree)>, ParseError>
+where
+    I: Iterator<Item = Result<Token, ParseError>>,
+{
+    let mut program = Vec::new();
+    while tokens.peek().is_some() {
+        program.push(parse_spanned(tokens)?);
+    }
+    Ok(program)
+}
+
+/// Parse a numeric literal, integers unless the token needs a float.
+/// `n/d` is an exact fraction, an integer if `d` divides `n`.
+pub fn parse_number(token: &str) -> Option<Expr> {
+    if let Ok(num) = token.parse::<i64>() {
+        Some(Expr::Int(num))
+    } else if let Some((num, den)) = token.split_once('/') {
+        let (num, den) = (num.parse::<i64>().ok()?, den.parse::<i64>().ok()?);
+        match reduce_ratio(num.into(), den.into())? {
+            (num, 1) => Some(Expr::Int(num)),
+            (num, den) => Some(Expr::Rational { num, den }),
+        }
+    } else if let Ok(num) = token.parse::<f64>() {
+        Some(Expr::Number(num))
+    } else {
+        None
+    }
+}
+
+fn parse_char(token: &Token) -> Result<char, ParseError> {
+    let name = &token.text[2..];
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => match name {
+            "space" => Ok(' '),
+            "newline" => Ok('\n'),
+            "tab" => Ok('\t'),
+            _ => Err(ParseError::UnexpectedToken(token.text.clone(), token.span)),
+        },
+    }
+}
+
+fn parse_string(token: &Token) -> Result<String, ParseError> {
+    let mut chars = token.text.chars().skip(1);
+    let mut result = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Ok(result),
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(c) => result.push(c),
+                None => break,
+            },
+            _ => result.push(c),
+        }
+    }
+    Err(ParseError::UnterminatedString(token.span))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().map(|token| token.text.as_str()).collect()
+    }
+
+    #[test]
+    fn tokenizer_yields_what_tokenize_collects() {
+        let source = "(define (f x)\n  `(,x ,@x #(1 \"a b\") #\\( #\\space)) ; comment\n'f";
+        let tokens = tokenize(source).unwrap();
+        let lazily: Vec<Token> = Tokenizer::from_chars(source.chars())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lazily, tokens);
+        assert_eq!(
+            texts(&tokens),
+            [
+                "(", "define", "(", "f", "x", ")", "`", "(", ",", "x", ",@", "x", "#(", "1",
+                "\"a b\"", ")", "#\\(", "#\\space", ")", ")", "'", "f"
+            ]
+        );
+        assert_eq!(tokens[6].span, Span { line: 2, column: 3 });
+        assert_eq!(tokens[20].span, Span { line: 3, column: 1 });
+    }
+
+    #[test]
+    fn parsing_lexes_only_what_it_needs() {
+        // the unterminated string after the first datum is never lexed
+        let mut tokens = Tokenizer::new("(a b) \"open").peekable();
+        assert_eq!(parse(&mut tokens), "(a b)".parse());
+        assert_eq!(
+            parse(&mut tokens),
+            Err(ParseError::UnterminatedString(Span { line: 1, column: 7 }))
+        );
+    }
+
+    #[test]
+    fn failing_source_ends_the_tokens() {
+        struct Failing(Chars<'static>);
+        impl CharSource for Failing {
+            fn next_char(&mut self) -> Result<Option<char>, ParseError> {
+                self.0.next().map(Some).ok_or(ParseError::UnexpectedEof)
+            }
+        }
+        let mut tokens = Tokenizer::from_chars(Failing("a bc".chars()));
+        assert_eq!(tokens.next().unwrap().unwrap().text, "a");
+        // `bc` was cut short
+        assert_eq!(tokens.next(), Some(Err(ParseError::UnexpectedEof)));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn tokenize_reader_reads_a_cursor() {
+        let source = "(display \"héllo ✓\") ; ünïcode\n#\\λ";
+        let tokens = tokenize_reader(io::Cursor::new(source.as_bytes())).unwrap();
+        assert_eq!(tokens, tokenize(source).unwrap());
+        assert_eq!(texts(&tokens), ["(", "display", "\"héllo ✓\"", ")", "#\\λ"]);
+        let err = tokenize_reader(io::Cursor::new(b"(a \xff)")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = tokenize_reader(io::Cursor::new(b"\"open")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reader_is_read_only_as_far_as_needed() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
             }
         }
+        let reader = io::Cursor::new(b"(a b) ".as_slice()).chain(Broken);
+        let mut tokens = Tokenizer::from_reader(reader).peekable();
+        assert_eq!(parse(&mut tokens), "(a b)".parse());
+        assert_eq!(
+            parse(&mut tokens),
+            Err(ParseError::Io(io::ErrorKind::Other, "broken".to_string()))
+        );
     }
 }
@@ -2,5 +2,7 @@ pub mod ast;
 pub mod env;
 pub mod eval;
 pub mod lexer;
+pub mod macros;
 pub mod value;
-pub mod repl;
\ No newline at end of file
+pub mod repl;
+pub mod symbol;
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::Expr,
+    symbol::{self, Symbol},
+};
+
+/// A `syntax-rules` macro. Expansion is not hygienic: template symbols are
+/// inserted as-is and resolved where the expansion is evaluated.
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub name: String,
+    pub literals: Vec<Symbol>,
+    /// `(pattern, template)` pairs, tried in order.
+    pub rules: Vec<(Expr, Expr)>,
+}
+
+/// What a pattern variable matched; variables under an ellipsis match once
+/// per repetition.
+#[derive(Debug, Clone)]
+enum Binding {
+    One(Expr),
+    Many(Vec<Binding>),
+}
+
+impl Macro {
+    /// Expand a use of the macro with the first rule whose pattern matches,
+    /// or `None` if none does.
+    pub fn expand(&self, expr: &Expr) -> Option<Expr> {
+        let Expr::List(form) = expr else {
+            return None;
+        };
+        for (pattern, template) in &self.rules {
+            // the keyword position of the pattern is ignored
+            let Expr::List(pattern) = pattern else {
+                continue;
+            };
+            let (Some((_, pattern)), Some((_, args))) = (pattern.split_first(), form.split_first())
+            else {
+                continue;
+            };
+            let mut bindings = HashMap::new();
+            if self.match_list(pattern, args, &mut bindings) {
+                return expand_template(template, &bindings);
+            }
+        }
+        None
+    }
+
+    fn match_pattern(
+        &self,
+        pattern: &Expr,
+        form: &Expr,
+        bindings: &mut HashMap<Symbol, Binding>,
+    ) -> bool {
+        match pattern {
+            Expr::Symbol(s) if *s == symbol::UNDERSCORE => true,
+            Expr::Symbol(s) if self.literals.contains(s) => {
+                matches!(form, Expr::Symbol(f) if f == s)
+            }
+            Expr::Symbol(s) => {
+                bindings.insert(*s, Binding::One(form.clone()));
+                true
+            }
+            Expr::List(pattern) => match form {
+                Expr::List(form) => self.match_list(pattern, form, bindings),
+                _ => false,
+            },
+            _ => pattern == form,
+        }
+    }
+
+    fn match_list(
+        &self,
+        pattern: &[Expr],
+        form: &[Expr],
+        bindings: &mut HashMap<Symbol, Binding>,
+    ) -> bool {
+        let ellipsis = pattern.iter().position(is_ellipsis);
+        let Some(pos) = ellipsis.filter(|&pos| pos > 0) else {
+            return pattern.len() == form.len()
+                && pattern
+                    .iter()
+                    .zip(form)
+                    .all(|(p, f)| self.match_pattern(p, f, bindings));
+        };
+        // `before... repeated ... after...`
+        let (before, repeated, after) =
+            (&pattern[..pos - 1], &pattern[pos - 1], &pattern[pos + 1..]);
+        if form.len() < before.len() + after.len() {
+            return false;
+        }
+        let repeat_end = form.len() - after.len();
+        if !self.match_list(before, &form[..before.len()], bindings)
+            || !self.match_list(after, &form[repeat_end..], bindings)
+        {
+            return false;
+        }
+        let mut matches = Vec::new();
+        for item in &form[before.len()..repeat_end] {
+            let mut item_bindings = HashMap::new();
+            if !self.match_pattern(repeated, item, &mut item_bindings) {
+                return false;
+            }
+            matches.push(item_bindings);
+        }
+        for var in self.pattern_vars(repeated) {
+            let repetitions = matches
+                .iter_mut()
+                .map(|m| m.remove(&var).unwrap_or(Binding::Many(Vec::new())))
+                .collect();
+            bindings.insert(var, Binding::Many(repetitions));
+        }
+        true
+    }
+
+    fn pattern_vars(&self, pattern: &Expr) -> Vec<Symbol> {
+        match pattern {
+            Expr::Symbol(s)
+                if *s == symbol::UNDERSCORE
+                    || *s == symbol::ELLIPSIS
+                    || self.literals.contains(s) =>
+            {
+                Vec::new()
+            }
+            Expr::Symbol(s) => vec![*s],
+            Expr::List(l) => l.iter().flat_map(|p| self.pattern_vars(p)).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn is_ellipsis(expr: &Expr) -> bool {
+    matches!(expr, Expr::Symbol(s) if *s == symbol::ELLIPSIS)
+}
+
+fn expand_template(template: &Expr, bindings: &HashMap<Symbol, Binding>) -> Option<Expr> {
+    match template {
+        Expr::Symbol(s) => match bindings.get(s) {
+            Some(Binding::One(expr)) => Some(expr.clone()),
+            // used without its ellipsis
+            Some(Binding::Many(_)) => None,
+            None => Some(template.clone()),
+        },
+        Expr::List(items) => {
+            let mut result = Vec::new();
+            let mut i = 0;
+            while i < items.len() {
+                if !items.get(i + 1).is_some_and(is_ellipsis) {
+                    result.push(expand_template(&items[i], bindings)?);
+                    i += 1;
+                    continue;
+                }
+                // `sub ...` repeats `sub` once for each match of its variables
+                let sub = &items[i];
+                let repeated: Vec<(&Symbol, &Vec<Binding>)> = bindings
+                    .iter()
+                    .filter_map(|(var, binding)| match binding {
+                        Binding::Many(reps) if mentions(sub, var) => Some((var, reps)),
+                        _ => None,
+                    })
+                    .collect();
+                let count = repeated.first()?.1.len();
+                if repeated.iter().any(|(_, reps)| reps.len() != count) {
+                    return None;
+                }
+                for k in 0..count {
+                    let mut iteration = bindings.clone();
+                    for (var, reps) in &repeated {
+                        iteration.insert(**var, reps[k].clone());
+                    }
+                    result.push(expand_template(sub, &iteration)?);
+                }
+                i += 2;
+            }
+            Some(Expr::List(result))
+        }
+        _ => Some(template.clone()),
+    }
+}
+
+fn mentions(template: &Expr, var: &Symbol) -> bool {
+    match template {
+        Expr::Symbol(s) => s == var,
+        Expr::List(l) => l.iter().any(|t| mentions(t, var)),
+        _ => false,
+    }
+}
@@ -1,27 +1,265 @@
-use std::io::Write;
+use std::{
+    fs::File,
+    io::{IsTerminal, Write},
+    process::ExitCode,
+};
 
 use scheme_parser::{
+    ast::{Expr, pretty_print},
     env::Env,
     eval::eval,
-    lexer::{parse, tokenize},
+    lexer::{ParseError, Token, Tokenizer, parse_spanned},
+    repl::{Balance, balance, eval_line, worth_printing},
+    symbol::Symbol,
+    value::Value,
 };
 
-fn main() {
+/// Native stack for the interpreter thread, enough for `eval` to reach
+/// `DEFAULT_MAX_DEPTH` even in debug builds.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+const USAGE: &str = "usage: scheme-parser [script] [-e | --eval expression]...";
+
+/// How much of `STACK_SIZE` evaluation may take, the rest being headroom.
+const MAX_STACK: usize = STACK_SIZE - 64 * 1024 * 1024;
+
+fn main() -> ExitCode {
+    // the main thread's stack is too small for deep (non-tail) recursion
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("failed to spawn the interpreter thread")
+        .join()
+        .unwrap_or(ExitCode::FAILURE)
+}
+
+fn run() -> ExitCode {
+    init_color();
+    let mut path = None;
+    let mut sources = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" | "--eval" => match args.next() {
+                Some(source) => sources.push(source),
+                None => {
+                    report(format_args!("Error: {arg} requires an expression"));
+                    return ExitCode::FAILURE;
+                }
+            },
+            flag if flag.starts_with('-') => {
+                report(format_args!("Error: unknown option {flag}\n{USAGE}"));
+                return ExitCode::FAILURE;
+            }
+            _ if path.is_some() => {
+                report(format_args!("Error: only one script can be run\n{USAGE}"));
+                return ExitCode::FAILURE;
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    if path.is_none() && sources.is_empty() {
+        repl();
+        return ExitCode::SUCCESS;
+    }
+    let mut env = new_env();
+    if let Some(path) = path {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                report(format_args!("Error: cannot read {path}: {e}"));
+                return ExitCode::FAILURE;
+            }
+        };
+        if run_source(Tokenizer::from_reader(file), &path, &mut env).is_none() {
+            return ExitCode::FAILURE;
+        }
+    }
+    // every `--eval` shares the same environment, in the order given
+    for source in &sources {
+        match run_source(Tokenizer::new(source), "--eval", &mut env) {
+            Some(Some(val)) if worth_printing(&val) => println!("{val}"),
+            Some(_) => {}
+            None => return ExitCode::FAILURE,
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Whether text written to `stream` gets ANSI colors: never when `NO_COLOR`
+/// is set to a non-empty value or when the stream isn't a terminal, so piped
+/// or redirected output stays plain text.
+fn color_for(stream: &impl IsTerminal) -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && stream.is_terminal()
+}
+
+/// Color values printed to stdout, the stream most output goes to.
+fn init_color() {
+    colored::control::set_override(color_for(&std::io::stdout()));
+}
+
+/// Print `msg` to stderr, colored according to stderr rather than stdout.
+fn report(msg: std::fmt::Arguments) {
+    colored::control::set_override(color_for(&std::io::stderr()));
+    eprintln!("{msg}");
+    init_color();
+}
+
+/// A fresh top-level environment, allowed the interpreter thread's stack.
+fn new_env() -> Env {
     let mut env = Env::new();
+    env.max_stack = MAX_STACK;
+    env
+}
+
+/// Evaluate the datums of `tokens` in turn, parsing each only once the one
+/// before it has been evaluated. Returns the value of the last one, or
+/// `None` after reporting the first error. `name` says where the tokens are
+/// read from.
+fn run_source<I>(tokens: I, name: &str, env: &mut Env) -> Option<Option<Value>>
+where
+    I: Iterator<Item = Result<Token, ParseError>>,
+{
+    let mut tokens = tokens.peekable();
+    let mut last = None;
+    while tokens.peek().is_some() {
+        let (expr, spans) = match parse_spanned(&mut tokens) {
+            Ok(datum) => datum,
+            Err(ParseError::Io(_, e)) => {
+                report(format_args!("Error: cannot read {name}: {e}"));
+                return None;
+            }
+            Err(e) => {
+                report(format_args!("Parse error: {e}"));
+                return None;
+            }
+        };
+        match eval(&expr, env) {
+            Ok(val) => last = Some(val),
+            Err(e) => {
+                report(format_args!("Error: {}", e.locate(&expr, &spans)));
+                return None;
+            }
+        }
+    }
+    Some(last)
+}
+
+fn repl() {
+    let mut env = new_env();
+    let mut input = String::new();
+    let mut stdout = std::io::stdout();
     loop {
-        print!("> ");
-        std::io::stdout().flush().unwrap();
-        let mut input = String::new();
-        if std::io::stdin().read_line(&mut input).is_err() {
+        // a continuation prompt while the datum is still open
+        let prompt = if input.is_empty() { "> " } else { "... " };
+        // stdout going away, e.g. a closed pipe, ends the session quietly
+        if write!(stdout, "{prompt}")
+            .and_then(|()| stdout.flush())
+            .is_err()
+        {
+            break;
+        }
+        match std::io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        match input.trim() {
+            ":env" => {
+                print_env(&env);
+                input.clear();
+                continue;
+            }
+            ":reset" => {
+                env.clear();
+                env = new_env();
+                println!("Environment reset");
+                input.clear();
+                continue;
+            }
+            ":builtins" => {
+                print_builtins(&env);
+                input.clear();
+                continue;
+            }
+            line if line.starts_with(":save ") => {
+                save_defs(&env, line[6..].trim());
+                input.clear();
+                continue;
+            }
+            line if line.starts_with(":pp ") => {
+                print_pretty(&line[4..]);
+                input.clear();
+                continue;
+            }
+            _ => {}
+        }
+        match balance(&input) {
+            Balance::Incomplete => continue,
+            Balance::Unbalanced => {
+                println!("Parse error: unbalanced ')'");
+                input.clear();
+                continue;
+            }
+            Balance::Complete => {}
+        }
+        if eval_line(&input, &mut env, &mut stdout).is_err() {
             break;
         }
-        let mut tokens = tokenize(&input);
-        let expr = parse(&mut tokens);
-        let result = eval(&expr, &mut env);
-        if result.is_err() {
-            println!("Error: {:#?}", result.as_ref().err().unwrap());
-        } else {
-            println!("{}", result.unwrap());
+        input.clear();
+    }
+}
+
+/// `:env`: the bindings of the top-level frame, builtins only counted.
+fn print_env(env: &Env) {
+    let vars = env.vars.borrow();
+    let mut names: Vec<Symbol> = vars
+        .iter()
+        .filter(|(_, val)| !matches!(val, Value::BuiltinFunction(_)))
+        .map(|(name, _)| *name)
+        .collect();
+    names.sort_by_key(|name| name.as_str());
+    for name in &names {
+        println!("{name}: {}", vars[name].type_name());
+    }
+    println!("({} builtins)", vars.len() - names.len());
+}
+
+/// `:pp <datum>`: the datum, unevaluated, laid out over several lines.
+fn print_pretty(source: &str) {
+    match source.parse::<Expr>() {
+        Ok(expr) => println!("{}", pretty_print(&expr, 80)),
+        Err(e) => println!("Parse error: {e}"),
+    }
+}
+
+/// `:save <path>`: write the user definitions to `path`, for `load` to read
+/// back later.
+fn save_defs(env: &Env, path: &str) {
+    let defs = env.dump_defs();
+    for line in defs.lines().filter(|line| line.starts_with("; skipped")) {
+        println!("Warning: {}", &line[2..]);
+    }
+    if let Err(e) = std::fs::write(path, defs) {
+        println!("Error: {path}: {e}");
+    }
+}
+
+/// `:builtins`: the names of all builtins, wrapped to a terminal's width.
+fn print_builtins(env: &Env) {
+    let mut line = String::new();
+    for name in env.builtin_names() {
+        if !line.is_empty() && line.len() + 1 + name.len() > 80 {
+            println!("{line}");
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
         }
+        line.push_str(&name);
+    }
+    if !line.is_empty() {
+        println!("{line}");
     }
 }
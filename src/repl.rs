@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+
+use crate::{
+    env::Env,
+    eval::eval,
+    lexer::{ParseError, Tokenizer, parse_program_spanned, tokenize},
+    value::Value,
+};
+
+/// Whether a chunk of REPL input holds complete datums.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Balance {
+    Complete,
+    /// An open list or string continues on the next line.
+    Incomplete,
+    /// A `)` closes more lists than were opened.
+    Unbalanced,
+}
+
+pub fn balance(input: &str) -> Balance {
+    let mut depth = 0usize;
+    let tokens = match tokenize(input) {
+        Ok(tokens) => tokens,
+        // a string literal still open at the end of the input
+        Err(ParseError::UnterminatedString(_)) => return Balance::Incomplete,
+        Err(_) => return Balance::Complete,
+    };
+    for token in &tokens {
+        match token.text.as_str() {
+            "(" | "#(" => depth += 1,
+            ")" if depth == 0 => return Balance::Unbalanced,
+            ")" => depth -= 1,
+            _ => {}
+        }
+    }
+    // a quote prefix still waits for its datum
+    let dangling = tokens
+        .last()
+        .is_some_and(|last| ["'", "`", ",", ",@"].contains(&last.text.as_str()));
+    if depth > 0 || dangling {
+        Balance::Incomplete
+    } else {
+        Balance::Complete
+    }
+}
+
+/// Evaluate every datum of a complete chunk of REPL input against `env`, in
+/// order, and write the value of the last one to `out`. The first error
+/// stops the rest of the input and is written instead, pointing at the
+/// datum that failed.
+pub fn eval_line(input: &str, env: &mut Env, out: &mut impl Write) -> io::Result<()> {
+    let program = match parse_program_spanned(&mut Tokenizer::new(input).peekable()) {
+        Ok(program) => program,
+        Err(e) => return writeln!(out, "Parse error: {e}"),
+    };
+    let mut last = Value::Unspecified;
+    for (expr, spans) in &program {
+        match eval(expr, env) {
+            Ok(val) => last = val,
+            Err(e) => return writeln!(out, "Error: {}", e.locate(expr, spans)),
+        }
+    }
+    if worth_printing(&last) {
+        writeln!(out, "{last}")?;
+    }
+    Ok(())
+}
+
+/// Whether to echo a result. Forms like `define` have no value worth
+/// printing, and neither does `(values)`.
+pub fn worth_printing(val: &Value) -> bool {
+    match val {
+        Value::Unspecified => false,
+        Value::Values(vals) => !vals.is_empty(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_lines(lines: &[&str]) -> String {
+        colored::control::set_override(false);
+        let mut env = Env::new();
+        let mut out = Vec::new();
+        for line in lines {
+            eval_line(line, &mut env, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn every_datum_of_a_line_is_evaluated() {
+        assert_eq!(
+            eval_lines(&["(define x 1) (define y (+ x 1)) (list x y)"]),
+            "(1 2)\n"
+        );
+        // definitions carry over to the next line
+        assert_eq!(eval_lines(&["(define x 1) (set! x 5)", "(+ x 1) x"]), "5\n");
+    }
+
+    #[test]
+    fn an_error_stops_the_line() {
+        assert_eq!(
+            eval_lines(&["(define x 1) (car x) (define x 2)", "x"]),
+            "Error: line 1, column 14: Type error: expected Pair, found 1 in (car x)\n1\n"
+        );
+        assert_eq!(
+            eval_lines(&["(define x 1) (+ x"]),
+            "Parse error: unexpected end of input\n"
+        );
+    }
+}
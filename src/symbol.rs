@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    ops::Deref,
+    sync::{LazyLock, Mutex},
+};
+
+/// An interned name. Symbols compare and hash as integers; the name itself
+/// is only looked up to print it, and is kept for the life of the process.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&sym) = self.ids.get(name) {
+            return sym;
+        }
+        let name: &'static str = Box::leak(name.into());
+        let sym = Symbol(self.names.len() as u32);
+        self.names.push(name);
+        self.ids.insert(name, sym);
+        sym
+    }
+}
+
+// Shared by all threads, so that an `Expr` parsed on one thread means the
+// same thing on another.
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(|| {
+    let mut interner = Interner {
+        ids: HashMap::new(),
+        names: Vec::new(),
+    };
+    for name in PREDEFINED {
+        interner.intern(name);
+    }
+    Mutex::new(interner)
+});
+
+/// Declares the names interned before any other, in order, each with a
+/// constant for its symbol so that `eval` can match on them.
+macro_rules! predefined {
+    ($($konst:ident => $name:literal),* $(,)?) => {
+        const PREDEFINED: &[&str] = &[$($name),*];
+        predefined!(@ids 0, $($konst),*);
+    };
+    (@ids $id:expr, $konst:ident $(, $rest:ident)*) => {
+        pub const $konst: Symbol = Symbol($id);
+        predefined!(@ids $id + 1, $($rest),*);
+    };
+    (@ids $id:expr,) => {};
+}
+
+predefined! {
+    DEFINE => "define",
+    SET => "set!",
+    DEFINE_SYNTAX => "define-syntax",
+    DEFINE_RECORD_TYPE => "define-record-type",
+    DEFINE_VALUES => "define-values",
+    BEGIN => "begin",
+    QUOTE => "quote",
+    QUASIQUOTE => "quasiquote",
+    UNQUOTE => "unquote",
+    UNQUOTE_SPLICING => "unquote-splicing",
+    DELAY => "delay",
+    PARAMETERIZE => "parameterize",
+    LAMBDA => "lambda",
+    CASE_LAMBDA => "case-lambda",
+    LET => "let",
+    AND => "and",
+    OR => "or",
+    NOT => "not",
+    IF => "if",
+    WHEN => "when",
+    UNLESS => "unless",
+    COND => "cond",
+    GUARD => "guard",
+    DO => "do",
+    CASE => "case",
+    ELSE => "else",
+    SYNTAX_RULES => "syntax-rules",
+    DOT => ".",
+    ELLIPSIS => "...",
+    UNDERSCORE => "_",
+}
+
+impl Symbol {
+    pub fn new(name: &str) -> Symbol {
+        INTERNER.lock().unwrap().intern(name)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.lock().unwrap().names[self.0 as usize]
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::new(name)
+    }
+}
+
+impl From<&Symbol> for Symbol {
+    fn from(sym: &Symbol) -> Self {
+        *sym
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|name| Symbol::new(&name))
+    }
+}
@@ -1,49 +1,661 @@
-use std::{cell::RefCell, fmt::Display, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
 
 use colored::Colorize;
 
-use crate::{ast::Expr, env::Env, eval::EvalError};
+use crate::{ast::Expr, env::Env, eval::EvalError, macros::Macro, symbol::Symbol};
 
 #[derive(Clone)]
 pub struct UserFunction {
-    pub params: Vec<String>,
+    pub params: Vec<Symbol>,
+    /// Bound to a list of the arguments after `params`, if any.
+    pub rest: Option<Symbol>,
     pub body: Expr,
     pub env: Rc<RefCell<Env>>,
     pub name: Option<String>,
 }
 
+/// The state of a `delay`ed expression, evaluated at most once.
+#[derive(Clone)]
+pub enum Promise {
+    Delayed { expr: Expr, env: Rc<RefCell<Env>> },
+    Forced(Value),
+}
+
+/// An escape-only continuation captured by `call/cc`, valid while that
+/// `call/cc` is still running.
+#[derive(Debug, Clone)]
+pub struct Continuation {
+    pub id: usize,
+}
+
+/// A record type made by `define-record-type`. Types are told apart by
+/// identity, not by name.
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+/// One of the procedures `define-record-type` generates for a type.
+#[derive(Debug, Clone)]
+pub struct RecordProc {
+    pub name: String,
+    pub record_type: Rc<RecordType>,
+    pub kind: RecordProcKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum RecordProcKind {
+    /// Takes the fields at these indices as arguments, the others start out
+    /// unspecified.
+    Constructor(Vec<usize>),
+    Predicate,
+    Accessor(usize),
+    Modifier(usize),
+}
+
+pub type BuiltinFn = fn(Vec<Value>, Expr, &mut Env) -> Result<Value, Box<EvalError>>;
+
 #[derive(Clone, Debug)]
 pub struct BuiltinFunc {
     pub name: String,
-    pub func: fn(Vec<Value>, Expr) -> Result<Value, Box<EvalError>>,
+    pub func: BuiltinFn,
+    /// How many arguments `apply` lets through to `func`, `max_args` being
+    /// `None` for no upper bound.
+    pub min_args: usize,
+    pub max_args: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Value {
+    Int(i64),
     Number(f64),
+    /// An exact fraction in lowest terms, `den` being greater than 1.
+    Rational { num: i64, den: i64 },
     Bool(bool),
+    String(String),
+    Char(char),
+    Symbol(String),
+    Pair(Rc<(Value, Value)>),
+    Vector(Rc<RefCell<Vec<Value>>>),
+    HashTable(Rc<RefCell<HashMap<ValueKey, Value>>>),
     BuiltinFunction(BuiltinFunc),
     Function(UserFunction),
-    Nil
+    /// The clauses of a `case-lambda`, one of which is picked by the number
+    /// of arguments of each call.
+    CaseLambda(Rc<Vec<UserFunction>>),
+    Macro(Macro),
+    Promise(Rc<RefCell<Promise>>),
+    Continuation(Continuation),
+    /// A dynamic parameter made by `make-parameter`, calling it returns its
+    /// current value and `parameterize` rebinds it.
+    Parameter(Rc<RefCell<Value>>),
+    /// An instance of a record type, its fields in the order they were
+    /// declared.
+    Record {
+        record_type: Rc<RecordType>,
+        fields: Rc<RefCell<Vec<Value>>>,
+    },
+    RecordProc(Rc<RecordProc>),
+    /// The results of `(values ...)` with other than one value, taken apart
+    /// again by `call-with-values`.
+    Values(Vec<Value>),
+    /// An error caught by an exception handler.
+    Condition(Rc<EvalError>),
+    /// What `read` returns at the end of its input.
+    Eof,
+    Nil,
+    /// The result of forms evaluated only for their effect, like `define`.
+    Unspecified
+}
+
+impl Value {
+    pub fn cons(car: Value, cdr: Value) -> Value {
+        Value::Pair(Rc::new((car, cdr)))
+    }
+
+    /// Build a proper list out of `items`.
+    pub fn list(items: Vec<Value>) -> Value {
+        Value::list_with_tail(items, Value::Nil)
+    }
+
+    /// Build a list out of `items` ending in `tail` instead of `()`, an
+    /// improper list unless `tail` is itself a list.
+    pub fn list_with_tail(items: Vec<Value>, tail: Value) -> Value {
+        items
+            .into_iter()
+            .rev()
+            .fold(tail, |tail, head| Value::cons(head, tail))
+    }
+
+    /// The name of the value's type, as shown to the user.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "Integer",
+            Value::Number(_) => "Number",
+            Value::Rational { .. } => "Rational",
+            Value::Bool(_) => "Bool",
+            Value::String(_) => "String",
+            Value::Char(_) => "Char",
+            Value::Symbol(_) => "Symbol",
+            Value::Pair(_) => "Pair",
+            Value::Vector(_) => "Vector",
+            Value::HashTable(_) => "HashTable",
+            Value::BuiltinFunction(_) => "Builtin",
+            Value::Function(_) | Value::CaseLambda(_) => "Function",
+            Value::Macro(_) => "Macro",
+            Value::Promise(_) => "Promise",
+            Value::Continuation(_) => "Continuation",
+            Value::Parameter(_) => "Parameter",
+            Value::Record { .. } => "Record",
+            Value::RecordProc(_) => "Builtin",
+            Value::Values(_) => "Values",
+            Value::Condition(_) => "Condition",
+            Value::Eof => "Eof",
+            Value::Nil => "Nil",
+            Value::Unspecified => "Unspecified",
+        }
+    }
+
+    /// `eq?`: atoms compare by value, pairs by identity. User functions are
+    /// copied around with their environment, so they never compare equal.
+    pub fn is_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Rational { num: a, den: b }, Value::Rational { num: c, den: d }) => {
+                a == c && b == d
+            }
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
+            (Value::Pair(a), Value::Pair(b)) => Rc::ptr_eq(a, b),
+            (Value::Vector(a), Value::Vector(b)) => Rc::ptr_eq(a, b),
+            (Value::HashTable(a), Value::HashTable(b)) => Rc::ptr_eq(a, b),
+            (Value::Promise(a), Value::Promise(b)) => Rc::ptr_eq(a, b),
+            (Value::Continuation(a), Value::Continuation(b)) => a.id == b.id,
+            (Value::Parameter(a), Value::Parameter(b)) => Rc::ptr_eq(a, b),
+            (Value::Record { fields: a, .. }, Value::Record { fields: b, .. }) => Rc::ptr_eq(a, b),
+            (Value::RecordProc(a), Value::RecordProc(b)) => Rc::ptr_eq(a, b),
+            (Value::CaseLambda(a), Value::CaseLambda(b)) => Rc::ptr_eq(a, b),
+            (Value::Condition(a), Value::Condition(b)) => Rc::ptr_eq(a, b),
+            (Value::BuiltinFunction(a), Value::BuiltinFunction(b)) => a.name == b.name,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Eof, Value::Eof) => true,
+            (Value::Unspecified, Value::Unspecified) => true,
+            _ => false,
+        }
+    }
+
+    /// `eqv?`: numbers and chars are immediate values here, so this agrees
+    /// with `eq?`; compound values still compare by identity.
+    pub fn is_eqv(&self, other: &Value) -> bool {
+        self.is_eq(other)
+    }
+
+    /// `equal?`: like `eqv?`, but pairs and vectors compare structurally.
+    pub fn is_equal(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Pair(a), Value::Pair(b)) => a.0.is_equal(&b.0) && a.1.is_equal(&b.1),
+            (Value::Vector(a), Value::Vector(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.is_equal(y))
+            }
+            _ => self.is_eqv(other),
+        }
+    }
+
+    /// Whether the value can be called.
+    pub fn is_procedure(&self) -> bool {
+        matches!(
+            self,
+            Value::BuiltinFunction(_)
+                | Value::Function(_)
+                | Value::CaseLambda(_)
+                | Value::Continuation(_)
+                | Value::Parameter(_)
+                | Value::RecordProc(_)
+        )
+    }
+
+    /// The elements of a proper list, or `None` if `self` isn't one.
+    pub fn list_to_vec(&self) -> Option<Vec<Value>> {
+        let mut items = Vec::new();
+        let mut cur = self;
+        loop {
+            match cur {
+                Value::Nil => return Some(items),
+                Value::Pair(pair) => {
+                    items.push(pair.0.clone());
+                    cur = &pair.1;
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<char> for Value {
+    fn from(c: char) -> Self {
+        Value::Char(c)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+/// The error from converting a `Value` into a Rust type it doesn't hold.
+#[derive(Debug, Clone)]
+pub struct TypeMismatch {
+    pub expected: &'static str,
+    pub found: Value,
+}
+
+impl Display for TypeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.found.type_name())
+    }
+}
+
+impl std::error::Error for TypeMismatch {}
+
+impl TryFrom<Value> for i64 {
+    type Error = TypeMismatch;
+
+    fn try_from(val: Value) -> Result<Self, Self::Error> {
+        match val {
+            Value::Int(n) => Ok(n),
+            found => Err(TypeMismatch { expected: "Integer", found }),
+        }
+    }
+}
+
+/// Integers convert too, as they would in arithmetic.
+impl TryFrom<Value> for f64 {
+    type Error = TypeMismatch;
+
+    fn try_from(val: Value) -> Result<Self, Self::Error> {
+        match val {
+            Value::Number(n) => Ok(n),
+            Value::Int(n) => Ok(n as f64),
+            found => Err(TypeMismatch { expected: "Number", found }),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = TypeMismatch;
+
+    fn try_from(val: Value) -> Result<Self, Self::Error> {
+        match val {
+            Value::Bool(b) => Ok(b),
+            found => Err(TypeMismatch { expected: "Bool", found }),
+        }
+    }
+}
+
+impl TryFrom<Value> for char {
+    type Error = TypeMismatch;
+
+    fn try_from(val: Value) -> Result<Self, Self::Error> {
+        match val {
+            Value::Char(c) => Ok(c),
+            found => Err(TypeMismatch { expected: "Char", found }),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = TypeMismatch;
+
+    fn try_from(val: Value) -> Result<Self, Self::Error> {
+        match val {
+            Value::String(s) => Ok(s),
+            found => Err(TypeMismatch { expected: "String", found }),
+        }
+    }
+}
+
+/// Format a float for display: the shortest digits that read back as the
+/// same float, whole numbers without a fractional part. Very large or small
+/// magnitudes use exponent notation.
+pub fn format_float(n: f64) -> String {
+    if n != 0.0 && n.is_finite() && !(1e-7..1e21).contains(&n.abs()) {
+        format!("{n:e}")
+    } else {
+        n.to_string()
+    }
+}
+
+/// A char as a literal that reads back as the same char: `#\a`, or a name
+/// like `#\space` for the whitespace the reader knows by name.
+pub fn char_literal(c: char) -> String {
+    match c {
+        ' ' => "#\\space".to_string(),
+        '\n' => "#\\newline".to_string(),
+        '\t' => "#\\tab".to_string(),
+        c => format!("#\\{c}"),
+    }
+}
+
+/// `num/den` in lowest terms with a positive denominator, or `None` if `den`
+/// is zero or a part doesn't fit in an `i64`.
+pub fn reduce_ratio(num: i128, den: i128) -> Option<(i64, i64)> {
+    if den == 0 {
+        return None;
+    }
+    let (mut a, mut b) = (num.unsigned_abs(), den.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    let (num, den) = (num / a as i128, den / a as i128);
+    let (num, den) = if den < 0 { (-num, -den) } else { (num, den) };
+    Some((num.try_into().ok()?, den.try_into().ok()?))
+}
+
+/// A `Value` used as a hash table key, hashed and compared with `equal?`
+/// semantics, except that NaN equals itself so it can be found again.
+/// Vectors hash by content, so mutating one that is a key loses its entry.
+/// Hash tables, promises, continuations, parameters, records, conditions and
+/// `case-lambda` procedures are compared and hashed by identity.
+#[derive(Debug, Clone)]
+pub struct ValueKey(pub Value);
+
+impl ValueKey {
+    /// Wrap `val` as a key, or `None` if it holds a user function, a macro or
+    /// multiple values. Those are never `equal?` to themselves, so they could
+    /// not be found again.
+    pub fn new(val: Value) -> Option<ValueKey> {
+        fn hashable(val: &Value) -> bool {
+            match val {
+                Value::Function(_) | Value::Macro(_) | Value::Values(_) => false,
+                Value::Pair(pair) => hashable(&pair.0) && hashable(&pair.1),
+                Value::Vector(v) => v.borrow().iter().all(hashable),
+                _ => true,
+            }
+        }
+        hashable(&val).then_some(ValueKey(val))
+    }
+}
+
+impl PartialEq for ValueKey {
+    fn eq(&self, other: &Self) -> bool {
+        fn key_equal(a: &Value, b: &Value) -> bool {
+            match (a, b) {
+                (Value::Number(a), Value::Number(b)) => a == b || (a.is_nan() && b.is_nan()),
+                (Value::Pair(a), Value::Pair(b)) => key_equal(&a.0, &b.0) && key_equal(&a.1, &b.1),
+                (Value::Vector(a), Value::Vector(b)) => {
+                    let (a, b) = (a.borrow(), b.borrow());
+                    a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| key_equal(x, y))
+                }
+                _ => a.is_eqv(b),
+            }
+        }
+        key_equal(&self.0, &other.0)
+    }
+}
+
+impl Eq for ValueKey {}
+
+impl Hash for ValueKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        fn hash_value<H: Hasher>(val: &Value, state: &mut H) {
+            std::mem::discriminant(val).hash(state);
+            match val {
+                Value::Int(n) => n.hash(state),
+                // 0.0 and -0.0 are equal and so are all NaNs, they must hash the same
+                Value::Number(n) => {
+                    let n = if n.is_nan() { f64::NAN } else if *n == 0.0 { 0.0 } else { *n };
+                    n.to_bits().hash(state)
+                }
+                Value::Rational { num, den } => (num, den).hash(state),
+                Value::Bool(b) => b.hash(state),
+                Value::String(s) | Value::Symbol(s) => s.hash(state),
+                Value::Char(c) => c.hash(state),
+                Value::Pair(pair) => {
+                    hash_value(&pair.0, state);
+                    hash_value(&pair.1, state);
+                }
+                Value::Vector(v) => v.borrow().iter().for_each(|val| hash_value(val, state)),
+                Value::HashTable(table) => std::ptr::hash(Rc::as_ptr(table), state),
+                Value::Promise(promise) => std::ptr::hash(Rc::as_ptr(promise), state),
+                Value::Condition(e) => std::ptr::hash(Rc::as_ptr(e), state),
+                Value::Continuation(k) => k.id.hash(state),
+                Value::Parameter(param) => std::ptr::hash(Rc::as_ptr(param), state),
+                Value::Record { fields, .. } => std::ptr::hash(Rc::as_ptr(fields), state),
+                Value::RecordProc(proc) => std::ptr::hash(Rc::as_ptr(proc), state),
+                Value::CaseLambda(clauses) => std::ptr::hash(Rc::as_ptr(clauses), state),
+                Value::BuiltinFunction(func) => func.name.hash(state),
+                _ => {}
+            }
+        }
+        hash_value(&self.0, state);
+    }
 }
 
 impl std::fmt::Debug for UserFunction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("UserFunction")
             .field("params", &self.params)
+            .field("rest", &self.rest)
             .field("body", &self.body)
             .finish()
     }
 }
 
+/// `#<procedure name (x y . rest)>`, the parameters written like those of
+/// the `lambda` it came from.
+impl Display for UserFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#<procedure ")?;
+        if let Some(name) = &self.name {
+            write!(f, "{name} ")?;
+        }
+        let params: Vec<&str> = self.params.iter().map(|p| p.as_str()).collect();
+        match (params.is_empty(), self.rest) {
+            (true, Some(rest)) => write!(f, "{rest}")?,
+            (false, Some(rest)) => write!(f, "({} . {rest})", params.join(" "))?,
+            (_, None) => write!(f, "({})", params.join(" "))?,
+        }
+        write!(f, ">")
+    }
+}
+
+impl std::fmt::Debug for Promise {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Promise::Delayed { expr, .. } => f.debug_tuple("Delayed").field(expr).finish(),
+            Promise::Forced(val) => f.debug_tuple("Forced").field(val).finish(),
+        }
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Value::Number(n) => write!(f, "{}", n.to_string().blue()),
-            Value::Bool(b) => write!(f, "{}", b.to_string().yellow()),
+            Value::Int(n) => write!(f, "{}", n.to_string().blue()),
+            Value::Number(n) => write!(f, "{}", format_float(*n).blue()),
+            Value::Rational { num, den } => write!(f, "{}", format!("{num}/{den}").blue()),
+            Value::Bool(b) => write!(f, "{}", if *b { "#t" } else { "#f" }.yellow()),
+            Value::String(s) => write!(f, "{}", format!("{s:?}").cyan()),
+            Value::Char(c) => write!(f, "{}", char_literal(*c).magenta()),
+            Value::Symbol(s) => write!(f, "{}", s.green()),
+            Value::Pair(pair) => {
+                write!(f, "({}", pair.0)?;
+                let mut tail = &pair.1;
+                loop {
+                    match tail {
+                        Value::Nil => break,
+                        Value::Pair(next) => {
+                            write!(f, " {}", next.0)?;
+                            tail = &next.1;
+                        }
+                        other => {
+                            write!(f, " . {other}")?;
+                            break;
+                        }
+                    }
+                }
+                write!(f, ")")
+            }
+            Value::Vector(v) => {
+                write!(f, "#(")?;
+                for (i, val) in v.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{val}")?;
+                }
+                write!(f, ")")
+            }
+            Value::HashTable(table) => {
+                write!(f, "{}", format!("#<hash-table {}>", table.borrow().len()).red())
+            }
             Value::BuiltinFunction(BuiltinFunc{name: n,..}) => write!(f, "{}", n.red()),
-            Value::Function(UserFunction{name: n,..}) => write!(f, "{}", n.as_ref().unwrap_or(&"".to_string()).red()),
+            Value::Function(func) => write!(f, "{}", func.to_string().red()),
+            Value::Macro(Macro { name, .. }) => write!(f, "{}", name.red()),
+            Value::Promise(_) => write!(f, "{}", "#<promise>".red()),
+            Value::Continuation(_) => write!(f, "{}", "#<continuation>".red()),
+            Value::Parameter(_) => write!(f, "{}", "#<parameter>".red()),
+            Value::Record { record_type, .. } => {
+                write!(f, "{}", format!("#<{}>", record_type.name).red())
+            }
+            Value::RecordProc(proc) => write!(f, "{}", proc.name.red()),
+            Value::CaseLambda(_) => write!(f, "{}", "#<case-lambda>".red()),
+            Value::Values(vals) => {
+                for (i, val) in vals.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{val}")?;
+                }
+                Ok(())
+            }
+            Value::Condition(e) => write!(f, "{e}"),
+            Value::Eof => write!(f, "{}", "#<eof>".white()),
             Value::Nil => write!(f, "{}", "nil".white().bold()),
+            Value::Unspecified => write!(f, "{}", "#<unspecified>".white()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_formatting() {
+        assert_eq!(format_float(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format_float(10.0 / 2.0), "5");
+        assert_eq!(format_float(2.5), "2.5");
+        assert_eq!(format_float(1e300), "1e300");
+        assert_eq!(format_float(-1.5e-9), "-1.5e-9");
+        for n in [1.0 / 3.0, 0.1 + 0.2, 2.0f64.sqrt(), 1e300 / 7.0, 1e-9 / 3.0] {
+            assert_eq!(format_float(n).parse::<f64>().unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn chars_print_as_literals() {
+        colored::control::set_override(false);
+        let chars = Value::list(vec![
+            Value::Char('a'),
+            Value::Symbol("a".to_string()),
+            Value::Char(' '),
+            Value::Char('\n'),
+            Value::Char('('),
+        ]);
+        let printed = chars.to_string();
+        assert_eq!(printed, "(#\\a a #\\space #\\newline #\\()");
+        assert_eq!(
+            printed.parse::<Expr>().unwrap(),
+            Expr::List(vec![
+                Expr::Char('a'),
+                Expr::Symbol("a".into()),
+                Expr::Char(' '),
+                Expr::Char('\n'),
+                Expr::Char('('),
+            ])
+        );
+    }
+
+    #[test]
+    fn from_rust_primitives() {
+        assert!(matches!(Value::from(3i64), Value::Int(3)));
+        assert!(matches!(Value::from(2.5), Value::Number(n) if n == 2.5));
+        assert!(matches!(Value::from(true), Value::Bool(true)));
+        assert!(matches!(Value::from('λ'), Value::Char('λ')));
+        assert!(matches!(Value::from("hi"), Value::String(s) if s == "hi"));
+        assert!(matches!(Value::from("hi".to_string()), Value::String(s) if s == "hi"));
+    }
+
+    #[test]
+    fn try_from_round_trips() {
+        assert_eq!(i64::try_from(Value::from(-7i64)).unwrap(), -7);
+        assert_eq!(f64::try_from(Value::from(2.5)).unwrap(), 2.5);
+        assert_eq!(f64::try_from(Value::from(2i64)).unwrap(), 2.0);
+        assert!(bool::try_from(Value::from(true)).unwrap());
+        assert_eq!(char::try_from(Value::from('a')).unwrap(), 'a');
+        assert_eq!(String::try_from(Value::from("s")).unwrap(), "s");
+
+        let err = i64::try_from(Value::from("3")).unwrap_err();
+        assert_eq!(err.expected, "Integer");
+        assert!(matches!(err.found, Value::String(ref s) if s == "3"));
+        assert_eq!(err.to_string(), "expected Integer, found String");
+        assert!(char::try_from(Value::from("a")).is_err());
+    }
+
+    #[test]
+    fn equal_keys_hash_equal() {
+        use std::hash::BuildHasher;
+        let state = std::collections::hash_map::RandomState::new();
+        let key = |val: Value| ValueKey::new(val).unwrap();
+        let pairs = [
+            (Value::Number(0.0), Value::Number(-0.0)),
+            (Value::Number(f64::NAN), Value::Number(-f64::NAN)),
+            (Value::list(vec![1.into(), "a".into()]), Value::list(vec![1.into(), "a".into()])),
+            (Value::from("s"), Value::from("s")),
+        ];
+        for (a, b) in pairs {
+            let (a, b) = (key(a), key(b));
+            assert_eq!(a, b);
+            assert_eq!(state.hash_one(&a), state.hash_one(&b));
         }
+        assert_ne!(key(Value::Int(1)), key(Value::Number(1.0)));
+        assert!(ValueKey::new(Value::Values(vec![])).is_none());
     }
 }
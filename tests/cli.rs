@@ -0,0 +1,133 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_scheme-parser"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("failed to run the interpreter")
+}
+
+fn run_with_input(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_scheme-parser"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run the interpreter");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn no_color_output_has_no_escape_sequences() {
+    let output = run(&["-e", "(list 1 2.5 \"s\" #\\a 'sym (lambda (x) x))"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "(1 2.5 \"s\" #\\a sym #<procedure (x)>)\n");
+    assert!(!stdout.contains('\x1b'));
+}
+
+#[test]
+fn no_color_errors_have_no_escape_sequences() {
+    let output = run(&["-e", "(car \"not a list\")"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Error: "));
+    assert!(!stderr.contains('\x1b'));
+}
+
+#[test]
+fn script_stops_at_the_first_error() {
+    let path = std::env::temp_dir().join(format!("scheme-parser-cli-{}.scm", std::process::id()));
+    std::fs::write(
+        &path,
+        "(display \"héllo\")\n(newline)\n(car 1)\n(display \"unreached\")\n",
+    )
+    .unwrap();
+    let output = run(&[path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "héllo\n");
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .starts_with("Error: line 3, column 1: ")
+    );
+}
+
+#[test]
+fn failing_script_exits_nonzero() {
+    let path = std::env::temp_dir().join(format!("scheme-parser-fail-{}.scm", std::process::id()));
+    std::fs::write(&path, "(define x 1)\n(undefined-function x)\n").unwrap();
+    let output = run(&[path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn bad_arguments_are_usage_errors() {
+    for args in [&["--help"][..], &["-x", "a.scm"], &["a.scm", "b.scm"]] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(1), "{args:?}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.contains("usage: scheme-parser"),
+            "{args:?}: {stderr}"
+        );
+    }
+}
+
+#[test]
+fn reset_forgets_definitions() {
+    let output = run_with_input(&[], "(define x 1)\nx\n:reset\nx\n(+ 1 2)\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("> 1\n"));
+    assert!(stdout.contains("Environment reset"));
+    assert!(stdout.contains("> 3\n"));
+    assert!(stdout.contains("Unbound symbol: x"));
+}
+
+#[test]
+fn repl_exits_quietly_when_stdout_closes() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_scheme-parser"))
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run the interpreter");
+    drop(child.stdout.take());
+    let mut stdin = child.stdin.take().unwrap();
+    // the interpreter may exit before reading everything
+    let _ = stdin.write_all("(+ 1 2)\n".repeat(1000).as_bytes());
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("panicked"), "{stderr}");
+    assert!(output.status.success());
+}
+
+#[test]
+fn read_skips_blank_lines() {
+    let output = run_with_input(
+        &["-e", "(list (read) (read) (read))"],
+        "\n; note\n42\n\n(a\n b)\n\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "(42 (a b) #<eof>)\n"
+    );
+}